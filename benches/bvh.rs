@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use ray_tracer::bvh::{BVHNode, SplitStrategy};
+use ray_tracer::color::Color;
+use ray_tracer::flat_bvh::FlatBVH;
+use ray_tracer::hittable::Hittable;
+use ray_tracer::hittable_list::HittableList;
+use ray_tracer::interval::Interval;
+use ray_tracer::material::Lambertian;
+use ray_tracer::quad::box3d;
+use ray_tracer::ray::Ray;
+use ray_tracer::sampler::IndependentSampler;
+use ray_tracer::sphere::Sphere;
+use ray_tracer::vec3::Point3;
+
+// The ground boxes and the cluster of spheres of the final scene.
+fn objects() -> HittableList {
+    let mut rng = StdRng::seed_from_u64(7);
+    let material = Arc::new(Lambertian::from_albedo(Color::new(0.5, 0.5, 0.5)));
+    let mut objects = HittableList::new();
+    for i in 0..20 {
+        for j in 0..20 {
+            let x0 = -1000.0 + i as f64 * 100.0;
+            let z0 = -1000.0 + j as f64 * 100.0;
+            objects.add(box3d(
+                Point3::new(x0, 0.0, z0),
+                Point3::new(x0 + 100.0, rng.random_range(1.0..101.0), z0 + 100.0),
+                material.clone(),
+            ));
+        }
+    }
+    for _ in 0..1000 {
+        let center = Point3::new(
+            rng.random_range(-100.0..65.0),
+            rng.random_range(270.0..435.0),
+            rng.random_range(395.0..560.0),
+        );
+        objects.add(Arc::new(Sphere::stationary(center, 10.0, material.clone())));
+    }
+    objects
+}
+
+fn rays() -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(11);
+    let origin = Point3::new(478.0, 278.0, -600.0);
+    (0..4096)
+        .map(|_| {
+            let target = Point3::new(
+                rng.random_range(-1000.0..1000.0),
+                rng.random_range(0.0..600.0),
+                rng.random_range(-1000.0..1000.0),
+            );
+            Ray::new(origin, target - origin, 0.0)
+        })
+        .collect()
+}
+
+fn strategies() -> [(&'static str, SplitStrategy); 2] {
+    [
+        ("median", SplitStrategy::Median),
+        ("sah", SplitStrategy::sah(4)),
+    ]
+}
+
+fn trace(bvh: &impl Hittable, rays: &[Ray]) -> usize {
+    let interval = Interval::new(0.001, f64::INFINITY);
+    let mut sampler = IndependentSampler::new(0);
+    rays.iter()
+        .filter(|ray| bvh.hit(ray, &interval, &mut sampler).is_some())
+        .count()
+}
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    for (name, strategy) in strategies() {
+        group.bench_function(BenchmarkId::new("tree", name), |b| {
+            b.iter(|| BVHNode::with_strategy(&mut objects(), black_box(strategy)))
+        });
+        group.bench_function(BenchmarkId::new("flat", name), |b| {
+            b.iter(|| FlatBVH::with_strategy(&mut objects(), black_box(strategy)))
+        });
+    }
+    group.finish();
+}
+
+fn hit(c: &mut Criterion) {
+    let rays = rays();
+    let mut group = c.benchmark_group("hit");
+    for (name, strategy) in strategies() {
+        let tree = BVHNode::with_strategy(&mut objects(), strategy);
+        group.bench_function(BenchmarkId::new("tree", name), |b| {
+            b.iter(|| trace(&tree, black_box(&rays)))
+        });
+        let flat = FlatBVH::with_strategy(&mut objects(), strategy);
+        group.bench_function(BenchmarkId::new("flat", name), |b| {
+            b.iter(|| trace(&flat, black_box(&rays)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build, hit);
+criterion_main!(benches);
@@ -56,6 +56,30 @@ impl AABB {
         )
     }
 
+    pub fn axis_interval(&self, axis: u8) -> &Interval {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            _ => &self.z,
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
     pub fn longest_axis(&self) -> u8 {
         [self.x.size(), self.y.size(), self.z.size()]
             .iter()
@@ -76,13 +100,37 @@ impl AABB {
             y: dy,
             z: dz,
         } = ray.direction();
+        // The ray is inside the box where it is inside all three slabs, so the
+        // interval narrows from one axis to the next.
+        let mut t_min = t.min;
+        let mut t_max = t.max;
         for (interval, direction, origin) in
             [(&self.x, dx, px), (&self.y, dy, py), (&self.z, dz, pz)]
         {
             let t0 = (interval.min - origin) / direction;
             let t1 = (interval.max - origin) / direction;
-            let t_min = f64::max(t.min, f64::min(t0, t1));
-            let t_max = f64::min(t.max, f64::max(t0, t1));
+            t_min = f64::max(t_min, f64::min(t0, t1));
+            t_max = f64::min(t_max, f64::max(t0, t1));
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Slab test taking the reciprocal of the ray direction, so that it can be
+    // computed once for the many boxes tested along a ray.
+    pub fn hit_inverse(&self, origin: &Point3, inverse_direction: &Vec3, t: &Interval) -> bool {
+        let mut t_min = t.min;
+        let mut t_max = t.max;
+        for axis in 0..3 {
+            let interval = self.axis_interval(axis);
+            let origin = origin.axis(axis);
+            let inverse = inverse_direction.axis(axis);
+            let t0 = (interval.min - origin) * inverse;
+            let t1 = (interval.max - origin) * inverse;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
             if t_max <= t_min {
                 return false;
             }
@@ -106,3 +154,27 @@ impl Add<AABB> for Vec3 {
         bbox + self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> AABB {
+        AABB::from_extremes(&Point3::new(0.0, 0.0, 0.0), &Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_hit_through_box() {
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(unit_box().hit(&ray, &Interval::new(0.0, f64::INFINITY)));
+        assert!(!unit_box().hit(&ray, &Interval::new(0.0, 0.5)));
+    }
+
+    #[test]
+    fn test_miss_when_slabs_do_not_overlap() {
+        // The ray is between the x planes for t in [1, 2] and between the y
+        // planes for t in [2.5, 3.5], so it passes beside the box.
+        let ray = Ray::new(Point3::new(-1.0, -2.5, 0.5), Vec3::new(1.0, 1.0, 0.0), 0.0);
+        assert!(!unit_box().hit(&ray, &Interval::new(0.0, f64::INFINITY)));
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::{color::Color, vec3::Vec3};
+
+// Arbitrary output variables: images of what the camera rays found, written
+// next to the colour for debugging and compositing. The albedo and the normals
+// also guide the denoiser.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Aov {
+    // The reflectance of the surface, or the colour of the background.
+    Albedo,
+    // The shading normal, facing the camera, or zero for the background.
+    Normal,
+    // The distance from the camera to the surface, or zero for the background.
+    Depth,
+    // The texture coordinates of the surface.
+    Uv,
+    // The IDs of the material and of the top-level object, or zero where there
+    // is none. They are taken from the first sample of each pixel rather than
+    // averaged.
+    MaterialId,
+    ObjectId,
+    // The light emitted by the surface, or the background.
+    Emission,
+    // The light that reaches the surface straight from the emitters, and
+    // after further bounces; with the emission, they add up to the colour.
+    Direct,
+    Indirect,
+    // The number of bounces of the paths.
+    RayDepth,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Emission => "emission",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::RayDepth => "ray_depth",
+        }
+    }
+
+    // The file next to `output` to write this AOV to, `image.albedo.png` for
+    // `image.png`.
+    pub fn path(&self, output: &Path) -> PathBuf {
+        let mut name = output.file_stem().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(self.name());
+        if let Some(extension) = output.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        output.with_file_name(name)
+    }
+}
+
+// What a camera ray found along its path, or the sum of it over the samples of
+// a pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Features {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    // 1 for rays that hit a surface and 0 for the others, which weighs the
+    // depth so that the background does not pull it towards zero.
+    pub coverage: f64,
+    pub uv: Vec3,
+    pub material_id: u32,
+    pub object_id: u32,
+    pub emission: Color,
+    pub direct: Color,
+    pub indirect: Color,
+    pub bounces: f64,
+}
+
+pub(crate) const FEATURE_VALUES: usize = 23;
+
+impl Features {
+    // Adds `sample` to the sums, keeping its IDs if it is the `first`.
+    pub fn accumulate(&mut self, sample: &Features, first: bool) {
+        self.albedo = self.albedo + sample.albedo;
+        self.normal = self.normal + sample.normal;
+        self.depth += sample.depth;
+        self.coverage += sample.coverage;
+        self.uv = self.uv + sample.uv;
+        self.emission = self.emission + sample.emission;
+        self.direct = self.direct + sample.direct;
+        self.indirect = self.indirect + sample.indirect;
+        self.bounces += sample.bounces;
+        if first {
+            self.material_id = sample.material_id;
+            self.object_id = sample.object_id;
+        }
+    }
+
+    // The value of `aov` for a pixel whose `count` samples summed to these
+    // features.
+    pub fn mean(&self, aov: Aov, count: u32) -> Color {
+        let grey = Color::grey;
+        if count == 0 {
+            return Color::default();
+        }
+        let count = count as f64;
+        match aov {
+            Aov::Albedo => self.albedo / count,
+            Aov::Normal => Color::from(self.normal / count),
+            Aov::Depth if self.coverage > 0.0 => grey(self.depth / self.coverage),
+            Aov::Depth => Color::default(),
+            Aov::Uv => Color::from(self.uv / count),
+            Aov::MaterialId => grey(self.material_id as f64),
+            Aov::ObjectId => grey(self.object_id as f64),
+            Aov::Emission => self.emission / count,
+            Aov::Direct => self.direct / count,
+            Aov::Indirect => self.indirect / count,
+            Aov::RayDepth => grey(self.bounces / count),
+        }
+    }
+
+    // The features as numbers, for checkpoints.
+    pub(crate) fn values(&self) -> [f64; FEATURE_VALUES] {
+        let triples = [
+            [self.albedo.r, self.albedo.g, self.albedo.b],
+            [self.normal.x, self.normal.y, self.normal.z],
+            [self.uv.x, self.uv.y, self.uv.z],
+            [self.emission.r, self.emission.g, self.emission.b],
+            [self.direct.r, self.direct.g, self.direct.b],
+            [self.indirect.r, self.indirect.g, self.indirect.b],
+        ];
+        let mut values = [0.0; FEATURE_VALUES];
+        for (index, triple) in triples.iter().enumerate() {
+            values[3 * index..3 * index + 3].copy_from_slice(triple);
+        }
+        values[18..].copy_from_slice(&[
+            self.depth,
+            self.coverage,
+            self.bounces,
+            self.material_id as f64,
+            self.object_id as f64,
+        ]);
+        values
+    }
+
+    pub(crate) fn from_values(values: &[f64; FEATURE_VALUES]) -> Self {
+        let vector = |index: usize| {
+            Vec3::new(
+                values[3 * index],
+                values[3 * index + 1],
+                values[3 * index + 2],
+            )
+        };
+        let color = |index: usize| Color::from(vector(index));
+        Features {
+            albedo: color(0),
+            normal: vector(1),
+            uv: vector(2),
+            emission: color(3),
+            direct: color(4),
+            indirect: color(5),
+            depth: values[18],
+            coverage: values[19],
+            bounces: values[20],
+            material_id: values[21] as u32,
+            object_id: values[22] as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            Aov::Albedo.path(Path::new("renders/image.png")),
+            Path::new("renders/image.albedo.png")
+        );
+        assert_eq!(
+            Aov::MaterialId.path(Path::new("image")),
+            Path::new("image.material_id")
+        );
+    }
+
+    #[test]
+    fn test_means() {
+        let hit = Features {
+            depth: 4.0,
+            coverage: 1.0,
+            material_id: 3,
+            object_id: 7,
+            bounces: 2.0,
+            ..Features::default()
+        };
+        let mut sum = Features::default();
+        sum.accumulate(&hit, true);
+        sum.accumulate(&Features::default(), false);
+        // The background does not count towards the depth, nor change the IDs.
+        assert_eq!(sum.mean(Aov::Depth, 2), Color::new(4.0, 4.0, 4.0));
+        assert_eq!(sum.mean(Aov::MaterialId, 2), Color::new(3.0, 3.0, 3.0));
+        assert_eq!(sum.mean(Aov::RayDepth, 2), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(Features::from_values(&sum.values()), sum);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, sync::Arc};
+use std::{cmp::Ordering, fmt, sync::Arc};
 
 use crate::{
     aabb::AABB,
@@ -6,18 +6,153 @@ use crate::{
     hittable_list::HittableList,
     interval::Interval,
     ray::Ray,
+    sampler::Sampler,
+    vec3::Point3,
 };
 
+// Relative costs of visiting a node and of intersecting a primitive, used to
+// estimate the cost of a tree with the surface area heuristic.
+const TRAVERSAL_COST: f64 = 0.125;
+const INTERSECTION_COST: f64 = 1.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SplitStrategy {
+    // Sorts the objects along the longest axis and splits them in halves,
+    // down to one or two objects per node.
+    #[default]
+    Median,
+    // Bins object centroids along the longest axis and splits where the
+    // surface area heuristic is lowest, stopping when a leaf of at most
+    // `max_leaf_size` objects is cheaper than any split.
+    Sah {
+        bins: usize,
+        max_leaf_size: usize,
+    },
+}
+
+impl SplitStrategy {
+    pub fn sah(max_leaf_size: usize) -> Self {
+        SplitStrategy::Sah {
+            bins: 12,
+            max_leaf_size,
+        }
+    }
+}
+
+// Shape of a tree, for comparing build strategies. Leaves are the children
+// that are not nodes of the tree, either single objects or lists of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BVHStats {
+    pub depth: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub leaf_object_count: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub sah_cost: f64,
+}
+
+impl BVHStats {
+    fn leaf(size: usize) -> Self {
+        BVHStats {
+            depth: 0,
+            node_count: 0,
+            leaf_count: 1,
+            leaf_object_count: size,
+            min_leaf_size: size,
+            max_leaf_size: size,
+            sah_cost: INTERSECTION_COST * size as f64,
+        }
+    }
+
+    fn node(bbox: &AABB, left: (&AABB, &BVHStats), right: (&AABB, &BVHStats)) -> Self {
+        let (left_bbox, left) = left;
+        let (right_bbox, right) = right;
+        let area = bbox.surface_area();
+        let child_cost = |child_bbox: &AABB, child: &BVHStats| {
+            if area > 0.0 {
+                child_bbox.surface_area() / area * child.sah_cost
+            } else {
+                child.sah_cost
+            }
+        };
+        BVHStats {
+            depth: 1 + left.depth.max(right.depth),
+            node_count: 1 + left.node_count + right.node_count,
+            leaf_count: left.leaf_count + right.leaf_count,
+            leaf_object_count: left.leaf_object_count + right.leaf_object_count,
+            min_leaf_size: left.min_leaf_size.min(right.min_leaf_size),
+            max_leaf_size: left.max_leaf_size.max(right.max_leaf_size),
+            sah_cost: TRAVERSAL_COST + child_cost(left_bbox, left) + child_cost(right_bbox, right),
+        }
+    }
+
+    pub fn mean_leaf_size(&self) -> f64 {
+        self.leaf_object_count as f64 / self.leaf_count as f64
+    }
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "depth {}, {} nodes, {} leaves of {} to {} objects ({:.2} on average), SAH cost {:.2}",
+            self.depth,
+            self.node_count,
+            self.leaf_count,
+            self.min_leaf_size,
+            self.max_leaf_size,
+            self.mean_leaf_size(),
+            self.sah_cost
+        )
+    }
+}
+
 pub struct BVHNode {
     left: Arc<dyn Hittable>,
     right: Arc<dyn Hittable>,
     bbox: AABB,
+    stats: BVHStats,
 }
 
 impl BVHNode {
     pub fn new(world: &mut HittableList) -> BVHNode {
-        let size = world.objects.len();
-        BVHNode::from_hittable_list(&mut world.objects, 0, size)
+        BVHNode::with_strategy(world, SplitStrategy::Median)
+    }
+
+    pub fn with_strategy(world: &mut HittableList, strategy: SplitStrategy) -> BVHNode {
+        match strategy {
+            SplitStrategy::Median => {
+                let size = world.objects.len();
+                BVHNode::from_hittable_list(&mut world.objects, 0, size)
+            }
+            SplitStrategy::Sah {
+                bins,
+                max_leaf_size,
+            } => {
+                let (root, stats) = BVHNode::split_sah(&mut world.objects, bins, max_leaf_size);
+                // The root is always a node, even when everything fits in a
+                // single leaf.
+                match root {
+                    SahTree::Node(node) => node,
+                    SahTree::Leaf(leaf) => BVHNode {
+                        bbox: *leaf.bounding_box(),
+                        left: leaf,
+                        right: Arc::new(HittableList::new()),
+                        stats: BVHStats {
+                            depth: 1,
+                            node_count: 1,
+                            sah_cost: TRAVERSAL_COST + stats.sah_cost,
+                            ..stats
+                        },
+                    },
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> &BVHStats {
+        &self.stats
     }
 
     fn from_hittable_list(
@@ -29,33 +164,80 @@ impl BVHNode {
         bbox = (start..end).fold(bbox, |bbox, index| {
             bbox.union(objects[index].bounding_box())
         });
-        let axis = bbox.longest_axis();
-
-        let comparator = match axis {
-            0 => BVHNode::box_compare_x,
-            1 => BVHNode::box_compare_y,
-            _ => BVHNode::box_compare_z,
-        };
         let span = end - start;
         let (left, right): (Arc<dyn Hittable>, Arc<dyn Hittable>);
+        let (left_stats, right_stats);
         match span {
             1 => {
                 left = objects[start].clone();
                 right = objects[start].clone();
+                (left_stats, right_stats) = (BVHStats::leaf(1), BVHStats::leaf(1));
             }
             2 => {
                 left = objects[start].clone();
                 right = objects[start + 1].clone();
+                (left_stats, right_stats) = (BVHStats::leaf(1), BVHStats::leaf(1));
             }
             _ => {
-                objects[start..end].sort_by(comparator);
-                let mid = start + span / 2;
-                left = Arc::new(BVHNode::from_hittable_list(objects, start, mid));
-                right = Arc::new(BVHNode::from_hittable_list(objects, mid, end));
+                let (half, _) = median_partition(&mut objects[start..end], &bbox);
+                let mid = start + half;
+                let left_node = BVHNode::from_hittable_list(objects, start, mid);
+                let right_node = BVHNode::from_hittable_list(objects, mid, end);
+                (left_stats, right_stats) = (left_node.stats, right_node.stats);
+                left = Arc::new(left_node);
+                right = Arc::new(right_node);
             }
         }
 
-        BVHNode { left, right, bbox }
+        let stats = BVHStats::node(
+            &bbox,
+            (left.bounding_box(), &left_stats),
+            (right.bounding_box(), &right_stats),
+        );
+        BVHNode {
+            left,
+            right,
+            bbox,
+            stats,
+        }
+    }
+
+    fn split_sah(
+        objects: &mut [Arc<dyn Hittable>],
+        bins: usize,
+        max_leaf_size: usize,
+    ) -> (SahTree, BVHStats) {
+        let bbox = objects.iter().fold(AABB::empty(), |bbox, object| {
+            bbox.union(object.bounding_box())
+        });
+        let Some((mid, _)) = sah_partition(objects, &bbox, bins, max_leaf_size) else {
+            let tree = match objects {
+                [object] => SahTree::Leaf(object.clone()),
+                _ => {
+                    let mut list = HittableList::new();
+                    objects.iter().for_each(|object| list.add(object.clone()));
+                    SahTree::Leaf(Arc::new(list))
+                }
+            };
+            return (tree, BVHStats::leaf(objects.len()));
+        };
+
+        let (left_objects, right_objects) = objects.split_at_mut(mid);
+        let (left, left_stats) = BVHNode::split_sah(left_objects, bins, max_leaf_size);
+        let (right, right_stats) = BVHNode::split_sah(right_objects, bins, max_leaf_size);
+        let (left, right) = (left.into_hittable(), right.into_hittable());
+        let stats = BVHStats::node(
+            &bbox,
+            (left.bounding_box(), &left_stats),
+            (right.bounding_box(), &right_stats),
+        );
+        let node = BVHNode {
+            left,
+            right,
+            bbox,
+            stats,
+        };
+        (SahTree::Node(node), stats)
     }
 
     fn box_compare_x(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
@@ -81,25 +263,246 @@ impl BVHNode {
     }
 }
 
+// Reorders `objects`, whose bounding box is `bbox`, so that the split with the
+// lowest surface area heuristic cost among `bins` centroid bins falls between
+// the returned index and the one before it. Also returns the axis of the
+// split. Returns `None` when intersecting the objects as a single leaf of at
+// most `max_leaf_size` objects is cheaper.
+pub(crate) fn sah_partition(
+    objects: &mut [Arc<dyn Hittable>],
+    bbox: &AABB,
+    bins: usize,
+    max_leaf_size: usize,
+) -> Option<(usize, u8)> {
+    let count = objects.len();
+    if count <= 1 {
+        return None;
+    }
+    let (min, max) = objects
+        .iter()
+        .map(|object| object.bounding_box().centroid())
+        .fold(
+            (
+                Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), centroid| (min.min(&centroid), max.max(&centroid)),
+        );
+    let axis = (0..3)
+        .max_by(|&a, &b| (max.axis(a) - min.axis(a)).total_cmp(&(max.axis(b) - min.axis(b))))
+        .unwrap();
+    let extent = Interval::new(min.axis(axis), max.axis(axis));
+    // With a single bin every object would fall on the same side.
+    let bins = bins.max(2);
+    let leaf_cost = INTERSECTION_COST * count as f64;
+    if extent.size() <= 0.0 {
+        // All centroids coincide, so no split separates the objects.
+        if count <= max_leaf_size {
+            return None;
+        }
+        return Some((count / 2, axis));
+    }
+    let bin_of = |object: &Arc<dyn Hittable>| {
+        let centroid = object.bounding_box().centroid().axis(axis);
+        let position = (centroid - extent.min) / extent.size();
+        ((position * bins as f64) as usize).min(bins - 1)
+    };
+    let mut bin_counts = vec![0; bins];
+    let mut bin_boxes = vec![AABB::empty(); bins];
+    for object in objects.iter() {
+        let bin = bin_of(object);
+        bin_counts[bin] += 1;
+        bin_boxes[bin] = bin_boxes[bin].union(object.bounding_box());
+    }
+
+    // Cost of splitting after each bin, sweeping from both ends.
+    let mut below = vec![(0, AABB::empty()); bins];
+    let mut running = (0, AABB::empty());
+    for bin in 0..bins {
+        running = (
+            running.0 + bin_counts[bin],
+            running.1.union(&bin_boxes[bin]),
+        );
+        below[bin] = running;
+    }
+    let area = bbox.surface_area();
+    let mut best = (f64::INFINITY, 0);
+    let mut above = (0, AABB::empty());
+    for bin in (1..bins).rev() {
+        above = (above.0 + bin_counts[bin], above.1.union(&bin_boxes[bin]));
+        let (below_count, below_box) = &below[bin - 1];
+        if *below_count == 0 || above.0 == 0 {
+            continue;
+        }
+        let cost = TRAVERSAL_COST
+            + INTERSECTION_COST
+                * (*below_count as f64 * below_box.surface_area()
+                    + above.0 as f64 * above.1.surface_area())
+                / area;
+        if cost < best.0 {
+            best = (cost, bin);
+        }
+    }
+
+    let (best_cost, split_bin) = best;
+    if count <= max_leaf_size && leaf_cost <= best_cost {
+        return None;
+    }
+    let mid = itertools::partition(objects.iter_mut(), |object| bin_of(object) < split_bin);
+    Some((mid, axis))
+}
+
+// Sorts `objects`, whose bounding box is `bbox`, along its longest axis and
+// returns the middle index and that axis.
+pub(crate) fn median_partition(objects: &mut [Arc<dyn Hittable>], bbox: &AABB) -> (usize, u8) {
+    let axis = bbox.longest_axis();
+    let comparator = match axis {
+        0 => BVHNode::box_compare_x,
+        1 => BVHNode::box_compare_y,
+        _ => BVHNode::box_compare_z,
+    };
+    objects.sort_by(comparator);
+    (objects.len() / 2, axis)
+}
+
+enum SahTree {
+    Node(BVHNode),
+    Leaf(Arc<dyn Hittable>),
+}
+
+impl SahTree {
+    fn into_hittable(self) -> Arc<dyn Hittable> {
+        match self {
+            SahTree::Node(node) => Arc::new(node),
+            SahTree::Leaf(leaf) => leaf,
+        }
+    }
+}
+
 impl Hittable for BVHNode {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
 
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         if !self.bbox.hit(ray, interval) {
             return None;
         }
 
-        let hit_left = self.left.hit(ray, interval);
+        let hit_left = self.left.hit(ray, interval, sampler);
         let closest_so_far = match &hit_left {
             Some(record) => record.t,
             None => interval.max,
         };
         let hit_right = self
             .right
-            .hit(ray, &Interval::new(interval.min, closest_so_far));
+            .hit(ray, &Interval::new(interval.min, closest_so_far), sampler);
 
         hit_right.or(hit_left)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color, material::Lambertian, sampler::IndependentSampler, sphere::Sphere, vec3::Vec3,
+    };
+
+    // Two tight clusters of small spheres far apart, with a few spheres on
+    // the line between them.
+    fn clustered_spheres() -> HittableList {
+        let material = Arc::new(Lambertian::from_albedo(Color::default()));
+        let mut list = HittableList::new();
+        for i in 0..40 {
+            let offset = (i % 20) as f64 * 0.05;
+            let x = if i < 20 { offset } else { 100.0 + offset };
+            list.add(Arc::new(Sphere::stationary(
+                Point3::new(x, 0.0, 0.0),
+                0.1,
+                material.clone(),
+            )));
+        }
+        for x in [25.0, 50.0, 75.0] {
+            list.add(Arc::new(Sphere::stationary(
+                Point3::new(x, 0.0, 0.0),
+                0.1,
+                material.clone(),
+            )));
+        }
+        list
+    }
+
+    fn closest_hits(bvh: &BVHNode, list: &HittableList) {
+        let mut sampler = IndependentSampler::new(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        for x in [0.0, 0.5, 25.0, 50.05, 100.3, 100.95, 60.0] {
+            let ray = Ray::new(Point3::new(x, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+            let expected = list
+                .hit(&ray, &interval, &mut sampler)
+                .map(|record| record.t);
+            let actual = bvh
+                .hit(&ray, &interval, &mut sampler)
+                .map(|record| record.t);
+            assert_eq!(actual, expected, "Different hit for x = {x}");
+        }
+    }
+
+    #[test]
+    fn test_sah_tree_finds_same_hits_as_list() {
+        let list = clustered_spheres();
+        let mut objects = clustered_spheres();
+        let bvh = BVHNode::with_strategy(&mut objects, SplitStrategy::sah(4));
+        closest_hits(&bvh, &list);
+        let stats = bvh.stats();
+        assert_eq!(stats.leaf_object_count, 43);
+        assert!(stats.max_leaf_size <= 4);
+    }
+
+    #[test]
+    fn test_sah_is_cheaper_than_median_split() {
+        let median = BVHNode::with_strategy(&mut clustered_spheres(), SplitStrategy::Median);
+        let sah = BVHNode::with_strategy(&mut clustered_spheres(), SplitStrategy::sah(4));
+        assert!(
+            sah.stats().sah_cost < median.stats().sah_cost,
+            "SAH {} against median {}",
+            sah.stats(),
+            median.stats()
+        );
+        assert!(sah.stats().node_count < median.stats().node_count);
+    }
+
+    #[test]
+    fn test_median_stats() {
+        let mut objects = clustered_spheres();
+        objects.objects.truncate(4);
+        let stats = *BVHNode::new(&mut objects).stats();
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.node_count, 3);
+        assert_eq!(stats.leaf_count, 4);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+    }
+
+    #[test]
+    fn test_nested_objects_stay_in_one_leaf() {
+        let mut sampler = IndependentSampler::new(0);
+        let material = Arc::new(Lambertian::from_albedo(Color::default()));
+        let mut objects = HittableList::new();
+        for radius in [1.0, 2.0, 3.0] {
+            objects.add(Arc::new(Sphere::stationary(
+                Point3::new(0.0, 0.0, 0.0),
+                radius,
+                material.clone(),
+            )));
+        }
+        let bvh = BVHNode::with_strategy(&mut objects, SplitStrategy::sah(4));
+        let ray = Ray::new(Point3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let record = bvh
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert!((record.t - 7.0).abs() < 1e-9);
+        let stats = bvh.stats();
+        assert_eq!((stats.depth, stats.node_count, stats.leaf_count), (1, 1, 1));
+        assert_eq!(stats.max_leaf_size, 3);
+    }
+}
@@ -1,20 +1,36 @@
 use std::cmp;
+use std::collections::BTreeMap;
 use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
-use itertools::Itertools;
-use rand::Rng;
-use rayon::prelude::*;
+use indicatif::{ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    color::{Color, format_color},
-    hittable::Hittable,
+    aov::Features,
+    checkpoint::{Checkpoint, CheckpointError, PixelSamples, fnv1a},
+    color::Color,
+    filter::{Filter, SplatBuffer},
+    framebuffer::Framebuffer,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
     interval::Interval,
+    material::ScatterRecord,
+    output::{OutputError, write_image, write_ppm},
+    pdf::{HittablePdf, Pdf},
     ray::Ray,
+    sampler::{Sampler, SamplerKind},
+    spectrum::Wavelengths,
+    tile::{TileOrder, tiles},
+    tone_map::ToneMapping,
     vec3::{Point3, Vec3},
 };
 
+// Samples taken for every pixel in each pass of `Camera::render_passes`.
+pub const DEFAULT_SAMPLES_PER_PASS: i32 = 16;
+
 #[derive(Serialize, Deserialize)]
 pub struct CameraSettings {
     pub aspect_ratio: f64,
@@ -28,12 +44,36 @@ pub struct CameraSettings {
     pub defocus_angle: f64,
     pub focus_distance: f64,
     pub background: Color,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adaptive_sampling: Option<AdaptiveSampling>,
+    #[serde(default)]
+    pub sampler: SamplerKind,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(default)]
+    pub spectral: bool,
+}
+
+// Once a pixel has `samples_per_pixel` samples, it keeps being sampled until
+// the relative standard error of its luminance falls below `noise_threshold`,
+// or it reaches `max_samples_per_pixel`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AdaptiveSampling {
+    pub noise_threshold: f64,
+    pub max_samples_per_pixel: i32,
 }
 
 pub struct Camera {
     image_width: i32,
     image_height: i32,
     samples_per_pixel: i32,
+    samples_per_pass: i32,
+    adaptive_sampling: Option<AdaptiveSampling>,
+    tile_size: u32,
+    tile_order: TileOrder,
+    sampler: SamplerKind,
+    filter: Filter,
+    spectral: bool,
     max_depth: i32,
     defocus_angle: f64,
     background: Color,
@@ -43,9 +83,11 @@ pub struct Camera {
     pixel_delta_v: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    seed: u64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: i32,
@@ -89,6 +131,13 @@ impl Camera {
             image_width,
             image_height,
             samples_per_pixel,
+            samples_per_pass: DEFAULT_SAMPLES_PER_PASS,
+            adaptive_sampling: None,
+            tile_size: 16,
+            tile_order: TileOrder::default(),
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            spectral: false,
             max_depth,
             defocus_angle,
             background,
@@ -98,11 +147,12 @@ impl Camera {
             pixel_delta_v,
             defocus_disk_u,
             defocus_disk_v,
+            seed: 0,
         }
     }
 
     pub fn from_settings(settings: CameraSettings) -> Self {
-        Camera::new(
+        let camera = Camera::new(
             settings.aspect_ratio,
             settings.image_width,
             settings.samples_per_pixel,
@@ -115,36 +165,332 @@ impl Camera {
             settings.focus_distance,
             settings.background,
         )
+        .with_sampler(settings.sampler)
+        .with_filter(settings.filter)
+        .with_spectral(settings.spectral);
+        match settings.adaptive_sampling {
+            Some(adaptive_sampling) => camera.with_adaptive_sampling(adaptive_sampling),
+            None => camera,
+        }
+    }
+
+    // Renders with the same seed produce the same image, whatever the number
+    // of threads.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Camera { seed, ..self }
+    }
+
+    // Chooses how the samples of each pixel are spread over the pixel, the
+    // lens, the shutter interval and the bounces of the paths.
+    pub fn with_sampler(self, sampler: SamplerKind) -> Self {
+        Camera { sampler, ..self }
+    }
+
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Camera { filter, ..self }
+    }
+
+    // Traces each path at a few wavelengths instead of in RGB, which lets
+    // dispersive materials split light into its colours.
+    pub fn with_spectral(self, spectral: bool) -> Self {
+        Camera { spectral, ..self }
+    }
+
+    pub fn with_adaptive_sampling(self, adaptive_sampling: AdaptiveSampling) -> Self {
+        Camera {
+            adaptive_sampling: Some(adaptive_sampling),
+            ..self
+        }
+    }
+
+    // Pixels are rendered in square tiles of `size` pixels, which threads take
+    // in `order`.
+    pub fn with_tiles(self, size: u32, order: TileOrder) -> Self {
+        Camera {
+            tile_size: size,
+            tile_order: order,
+            ..self
+        }
+    }
+
+    pub fn with_samples_per_pass(self, samples_per_pass: i32) -> Self {
+        Camera {
+            samples_per_pass,
+            ..self
+        }
+    }
+
+    pub fn render<W: Write>(&self, world: &impl Hittable, lights: &HittableList, out: &mut W) {
+        write_ppm(&self.render_framebuffer(world, lights), out).unwrap();
+    }
+
+    pub fn render_to_file(
+        &self,
+        world: &impl Hittable,
+        lights: &HittableList,
+        file: &Path,
+    ) -> Result<(), OutputError> {
+        write_image(
+            file,
+            &self.render_framebuffer(world, lights),
+            &ToneMapping::default(),
+        )
+    }
+
+    // `lights` lists the emitters that diffuse bounces sample directly, in
+    // addition to following their material's distribution. It may be empty.
+    pub fn render_framebuffer(&self, world: &impl Hittable, lights: &HittableList) -> Framebuffer {
+        let mut checkpoint = self.new_checkpoint(&[]);
+        self.render_passes(world, lights, &mut checkpoint, None)
+            .expect("Rendering without a checkpoint file cannot fail");
+        checkpoint.framebuffer()
     }
 
-    pub fn render<W: Write>(&self, world: &(impl Hittable + Send + Sync), out: &mut W) {
-        let progress_bar = self.get_progress_bar();
-        let pixels = (0..self.image_height)
-            .cartesian_product(0..self.image_width)
-            .collect::<Vec<(i32, i32)>>()
-            .into_par_iter()
-            .progress_with(progress_bar)
-            .map(|(j, i)| {
-                let pixel_color: Color = (0..self.samples_per_pixel)
-                    .into_iter()
-                    .map(|_| self.ray_color(&self.get_ray(i, j), self.max_depth, world))
-                    .sum::<Color>()
-                    / self.samples_per_pixel as f64;
-                format_color(&pixel_color)
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
-        write!(
-            out,
-            "P3\n{} {}\n255\n{}",
-            self.image_width, self.image_height, pixels
+    // An empty checkpoint for rendering `scene`, which identifies the objects
+    // in the settings hash; the camera adds its own settings and seed.
+    pub fn new_checkpoint(&self, scene: &[u8]) -> Checkpoint {
+        Checkpoint::new(
+            self.image_width as u32,
+            self.image_height as u32,
+            self.settings_hash(scene),
         )
-        .unwrap();
     }
 
-    fn get_progress_bar(&self) -> ProgressBar {
+    pub fn resume_checkpoint(
+        &self,
+        file: &Path,
+        scene: &[u8],
+    ) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::read(file, self.settings_hash(scene))
+    }
+
+    // Takes samples in passes of up to `samples_per_pass` per pixel until
+    // every pixel of `checkpoint` has `samples_per_pixel`, and with adaptive
+    // sampling until the noisy ones have converged, writing it to
+    // `checkpoint_file` after each pass. The samples of a pixel do not depend
+    // on whether earlier passes were taken in this run or a previous one.
+    pub fn render_passes(
+        &self,
+        world: &impl Hittable,
+        lights: &HittableList,
+        checkpoint: &mut Checkpoint,
+        checkpoint_file: Option<&Path>,
+    ) -> Result<(), CheckpointError> {
+        let pass_size = self.samples_per_pass.max(1) as u32;
+        let max_samples = match self.adaptive_sampling {
+            Some(adaptive_sampling) => adaptive_sampling
+                .max_samples_per_pixel
+                .max(self.samples_per_pixel),
+            None => self.samples_per_pixel,
+        }
+        .max(0) as u32;
+        // The bar counts pixel passes, assuming that no pixel converges; the
+        // passes of pixels that do are taken off as they are skipped.
+        let max_passes = checkpoint
+            .sample_counts()
+            .iter()
+            .map(|count| max_samples.saturating_sub(*count).div_ceil(pass_size) as u64)
+            .collect::<Vec<u64>>();
+        let progress_bar = self.get_progress_bar(max_passes.iter().sum());
+        let mut passes_taken = vec![0; max_passes.len()];
+        let width = self.image_width as u32;
+        let tiles = tiles(
+            width,
+            self.image_height as u32,
+            self.tile_size,
+            self.tile_order,
+        );
+        loop {
+            let plan = (0..max_passes.len())
+                .map(|index| self.samples_to_take(checkpoint, index, pass_size))
+                .collect::<Vec<u32>>();
+            if plan.iter().all(|count| *count == 0) {
+                break;
+            }
+            let first_samples = checkpoint.sample_counts().to_vec();
+            // Threads take the next tile in order as soon as they are free.
+            // Finished tiles are added to the checkpoint in order, so that the
+            // sums of the pixels that neighbouring tiles both splat onto do
+            // not depend on which thread finished first.
+            let next_tile = AtomicUsize::new(0);
+            let merge = Mutex::new(TileMerge {
+                checkpoint: &mut *checkpoint,
+                next_tile: 0,
+                finished: BTreeMap::new(),
+            });
+            rayon::scope(|scope| {
+                for _ in 0..rayon::current_num_threads() {
+                    scope.spawn(|_| {
+                        loop {
+                            let number = next_tile.fetch_add(1, Ordering::Relaxed);
+                            let Some(tile) = tiles.get(number) else {
+                                break;
+                            };
+                            let mut splats = SplatBuffer::new(
+                                self.filter,
+                                tile,
+                                width,
+                                self.image_height as u32,
+                            );
+                            let pixels = tile
+                                .pixel_indices(width)
+                                .filter(|index| plan[*index] > 0)
+                                .map(|index| {
+                                    let samples = self.sample_pixel(
+                                        world,
+                                        lights,
+                                        index,
+                                        first_samples[index],
+                                        plan[index],
+                                        &mut splats,
+                                    );
+                                    (index, samples)
+                                })
+                                .collect::<Vec<(usize, PixelSamples)>>();
+                            let mut merge = merge.lock().unwrap();
+                            merge.finished.insert(number, (pixels, splats));
+                            while let Some((pixels, splats)) = merge.take_next() {
+                                for (index, samples) in &pixels {
+                                    merge.checkpoint.add_samples(*index, samples);
+                                }
+                                splats
+                                    .splats()
+                                    .for_each(|splat| merge.checkpoint.add_splat(&splat));
+                                progress_bar.inc(pixels.len() as u64);
+                            }
+                        }
+                    });
+                }
+            });
+            for (index, count) in plan.iter().enumerate() {
+                if *count > 0 {
+                    passes_taken[index] += 1;
+                }
+            }
+            let converged_passes = (0..max_passes.len())
+                .filter(|index| self.samples_to_take(checkpoint, *index, pass_size) == 0)
+                .map(|index| max_passes[index] - passes_taken[index])
+                .sum::<u64>();
+            progress_bar.set_length(max_passes.iter().sum::<u64>() - converged_passes);
+            if let Some(file) = checkpoint_file {
+                checkpoint.write(file)?;
+            }
+        }
+        progress_bar.finish();
+        Ok(())
+    }
+
+    // The number of samples the next pass takes for the pixel at `index`.
+    fn samples_to_take(&self, checkpoint: &Checkpoint, index: usize, pass_size: u32) -> u32 {
+        let count = checkpoint.sample_counts()[index];
+        let minimum = self.samples_per_pixel.max(0) as u32;
+        if count < minimum {
+            return (minimum - count).min(pass_size);
+        }
+        match self.adaptive_sampling {
+            Some(adaptive_sampling)
+                if checkpoint.relative_error(index) > adaptive_sampling.noise_threshold =>
+            {
+                let maximum = adaptive_sampling.max_samples_per_pixel.max(0) as u32;
+                maximum.saturating_sub(count).min(pass_size)
+            }
+            _ => 0,
+        }
+    }
+
+    fn sample_pixel(
+        &self,
+        world: &impl Hittable,
+        lights: &HittableList,
+        index: usize,
+        first_sample: u32,
+        count: u32,
+        splats: &mut SplatBuffer,
+    ) -> PixelSamples {
+        let i = (index % self.image_width as usize) as i32;
+        let j = (index / self.image_width as usize) as i32;
+        let sampler = &mut *self.sampler.for_pixel(
+            self.seed,
+            i as u32,
+            j as u32,
+            self.image_width as u32,
+            self.samples_per_pixel.max(1) as u32,
+        );
+        let mut samples = PixelSamples::default();
+        let mut features = Features::default();
+        for sample in 0..count {
+            sampler.start_sample(first_sample as u64 + sample as u64);
+            let (x, y) = sampler.random_2d();
+            let offset = (x - 0.5, y - 0.5);
+            let mut ray = self.get_ray(i, j, offset, sampler);
+            let wavelengths = self.spectral.then(|| Wavelengths::sample(sampler.random()));
+            ray = ray.with_wavelengths(wavelengths);
+            let mut radiance = self.ray_color(
+                &ray,
+                self.max_depth,
+                world,
+                lights,
+                None,
+                sampler,
+                &mut features,
+            );
+            if let Some(wavelengths) = wavelengths {
+                radiance = wavelengths.to_rgb(&radiance);
+                for color in [
+                    &mut features.emission,
+                    &mut features.direct,
+                    &mut features.indirect,
+                ] {
+                    *color = wavelengths.to_rgb(color);
+                }
+            }
+            let radiance = remove_nans(radiance);
+            for color in [
+                &mut features.emission,
+                &mut features.direct,
+                &mut features.indirect,
+            ] {
+                *color = remove_nans(*color);
+            }
+            samples.add(radiance, &features);
+            splats.add(i as f64 + offset.0, j as f64 + offset.1, radiance);
+        }
+        samples
+    }
+
+    // Identifies everything the image depends on but the number of samples.
+    fn settings_hash(&self, scene: &[u8]) -> u64 {
+        let mut bytes = Vec::new();
+        for value in [self.image_width, self.image_height, self.max_depth] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.defocus_angle.to_le_bytes());
+        for component in [self.background.r, self.background.g, self.background.b] {
+            bytes.extend_from_slice(&component.to_le_bytes());
+        }
+        for vector in [
+            self.center,
+            self.pixel00_loc,
+            self.pixel_delta_u,
+            self.pixel_delta_v,
+            self.defocus_disk_u,
+            self.defocus_disk_v,
+        ] {
+            for component in [vector.x, vector.y, vector.z] {
+                bytes.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(self.sampler as u8);
+        bytes.push(self.spectral as u8);
+        bytes.extend_from_slice(&serde_json::to_vec(&self.filter).unwrap());
+        bytes.extend_from_slice(scene);
+        fnv1a(&bytes)
+    }
+
+    fn get_progress_bar(&self, passes: u64) -> ProgressBar {
         let total_number_of_pixels = self.image_width as u64 * self.image_height as u64;
-        let progress_bar = ProgressBar::new(total_number_of_pixels);
+        let progress_bar = ProgressBar::new(passes * total_number_of_pixels);
         progress_bar.set_style(
             ProgressStyle::default_bar()
                 .template(
@@ -156,49 +502,564 @@ impl Camera {
         progress_bar
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let mut rng = rand::rng();
-        let offset = Vec3::new(
-            rng.random_range(-0.5..0.5),
-            rng.random_range(-0.5..0.5),
-            0.0,
-        );
+    // A ray through the point at `offset` from the centre of the pixel.
+    fn get_ray(&self, i: i32, j: i32, offset: (f64, f64), sampler: &mut dyn Sampler) -> Ray {
         let pixel_sample = self.pixel00_loc
-            + (i as f64 + offset.x) * self.pixel_delta_u
-            + (j as f64 + offset.y) * self.pixel_delta_v;
+            + (i as f64 + offset.0) * self.pixel_delta_u
+            + (j as f64 + offset.1) * self.pixel_delta_v;
         let origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let direction = pixel_sample - origin;
-        let time = rng.random_range(0.0..1.0);
+        let time = sampler.random();
         Ray::new(origin, direction, time)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let point = Point3::random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler) -> Point3 {
+        let point = Point3::random_in_unit_disk(sampler);
         self.center + (point.x * self.defocus_disk_u) + (point.y * self.defocus_disk_v)
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, world: &impl Hittable) -> Color {
+    // `material_pdf` is the density with which the previous bounce sampled
+    // `ray` from its material, when the lights could also have been sampled in
+    // that direction; emission found along `ray` is then weighted against light
+    // sampling with multiple importance sampling. `features` records what the
+    // ray found, with the colour split into the light emitted where it lands,
+    // the direct lighting and the indirect lighting there.
+    #[allow(clippy::too_many_arguments)]
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: i32,
+        world: &impl Hittable,
+        lights: &HittableList,
+        material_pdf: Option<f64>,
+        sampler: &mut dyn Sampler,
+        features: &mut Features,
+    ) -> Color {
+        *features = Features::default();
         if depth <= 0 {
             return Color::new(0.0, 0.0, 0.0);
         }
-        match world.hit(ray, &Interval::new(0.001, f64::INFINITY)) {
+        match world.hit(ray, &Interval::new(0.001, f64::INFINITY), sampler) {
             Some(record) => {
-                let color_from_emission =
-                    record.material.emitted(record.u, record.v, &record.point);
-                match record.material.scatter(ray, &record) {
-                    Some((attenuation, scattered_ray)) => {
-                        let color_from_scatter =
-                            attenuation * self.ray_color(&scattered_ray, depth - 1, world);
-                        color_from_emission + color_from_scatter
-                    }
-                    None => color_from_emission,
+                let mut emitted = record.material.emitted(&record);
+                if let Some(material_pdf) = material_pdf {
+                    let light_pdf = lights.pdf_value(ray.origin(), ray.direction());
+                    emitted = power_heuristic(material_pdf, light_pdf) * emitted;
                 }
+                // Everything found at the hit fades on the way back through
+                // the medium the ray travels in.
+                let transmittance = spectral(ray, &ray.transmittance(record.t));
+                let color_from_emission = transmittance * spectral(ray, &emitted);
+                let scatter_record = record.material.scatter(ray, &record, sampler);
+                // Lights have no reflectance, so their albedo is their
+                // emission clipped to white.
+                let albedo = match &scatter_record {
+                    Some(ScatterRecord::Specular { attenuation, .. })
+                    | Some(ScatterRecord::Sampled { attenuation, .. }) => *attenuation,
+                    None => emitted
+                        .max(&Color::default())
+                        .min(&Color::new(1.0, 1.0, 1.0)),
+                };
+                *features = Features {
+                    albedo,
+                    normal: record.normal,
+                    depth: record.t * ray.direction().length(),
+                    coverage: 1.0,
+                    uv: Vec3::new(record.u, record.v, 0.0),
+                    material_id: record.material.id(),
+                    object_id: record.object_id,
+                    emission: color_from_emission,
+                    ..Features::default()
+                };
+                let mut next = Features::default();
+                let (color_from_lights, color_from_scatter, throughput) = match scatter_record {
+                    Some(ScatterRecord::Specular {
+                        attenuation,
+                        ray: scattered_ray,
+                    }) => {
+                        // Materials that do not choose the wavelengths of the
+                        // ray keep those of the incoming one.
+                        let scattered_ray = match scattered_ray.wavelengths() {
+                            Some(_) => scattered_ray,
+                            None => scattered_ray.with_wavelengths(ray.wavelengths().copied()),
+                        };
+                        // Nor, unless they are dielectrics, the medium it
+                        // travels through.
+                        let scattered_ray = match scattered_ray.absorption() {
+                            Some(_) => scattered_ray,
+                            None => scattered_ray.with_absorption(ray.absorption().copied()),
+                        };
+                        let mut attenuation = spectral(ray, &attenuation);
+                        if let (Some(before), Some(after)) =
+                            (ray.wavelengths(), scattered_ray.wavelengths())
+                        {
+                            attenuation = attenuation * before.termination_weight(after);
+                        }
+                        let color_from_scatter = attenuation
+                            * self.ray_color(
+                                &scattered_ray,
+                                depth - 1,
+                                world,
+                                lights,
+                                None,
+                                sampler,
+                                &mut next,
+                            );
+                        (Color::default(), color_from_scatter, attenuation)
+                    }
+                    Some(ScatterRecord::Sampled { attenuation, pdf }) => {
+                        let scatter = Scatter {
+                            ray_in: ray,
+                            record: &record,
+                            attenuation,
+                            pdf: pdf.as_ref(),
+                        };
+                        // The last bounce cannot reach a light through its
+                        // material either, so it does not sample them.
+                        let color_from_lights = if depth > 1 && !lights.objects.is_empty() {
+                            self.sample_lights(&scatter, world, lights, sampler)
+                        } else {
+                            Color::new(0.0, 0.0, 0.0)
+                        };
+                        let (color_from_scatter, throughput) = self
+                            .sample_material(&scatter, depth, world, lights, sampler, &mut next);
+                        (color_from_lights, color_from_scatter, throughput)
+                    }
+                    None => return color_from_emission,
+                };
+                let (color_from_lights, color_from_scatter, throughput) = (
+                    transmittance * color_from_lights,
+                    transmittance * color_from_scatter,
+                    transmittance * throughput,
+                );
+                // What the scattered ray finds emitted is direct lighting too.
+                features.direct = color_from_lights + throughput * next.emission;
+                features.indirect = throughput * (next.direct + next.indirect);
+                features.bounces = 1.0 + next.bounces;
+                color_from_emission + color_from_lights + color_from_scatter
+            }
+            None => {
+                let background = spectral(ray, &self.background);
+                features.albedo = self.background;
+                features.emission = background;
+                background
             }
-            None => self.background,
         }
     }
+
+    // Next-event estimation: a shadow ray towards a random point on the lights.
+    fn sample_lights(
+        &self,
+        scatter: &Scatter,
+        world: &impl Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let record = scatter.record;
+        let light_pdf = HittablePdf::new(lights, record.point);
+        let shadow_ray = Ray::new(
+            record.point,
+            light_pdf.generate(sampler),
+            scatter.ray_in.time(),
+        )
+        .with_wavelengths(scatter.ray_in.wavelengths().copied())
+        .with_absorption(scatter.ray_in.absorption().copied());
+        let light_pdf_value = light_pdf.value(shadow_ray.direction());
+        if light_pdf_value <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let scattering = spectral(
+            scatter.ray_in,
+            &record
+                .material
+                .scattering(scatter.ray_in, record, &shadow_ray, &scatter.attenuation),
+        );
+        if scattering == Color::default() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        match world.hit(&shadow_ray, &Interval::new(0.001, f64::INFINITY), sampler) {
+            Some(light_record) => {
+                let emitted = spectral(&shadow_ray, &light_record.material.emitted(&light_record))
+                    * spectral(&shadow_ray, &shadow_ray.transmittance(light_record.t));
+                let material_pdf_value = scatter.pdf.value(shadow_ray.direction());
+                let weight = power_heuristic(light_pdf_value, material_pdf_value);
+                weight * scattering * emitted / light_pdf_value
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // The light scattered along a direction sampled from the material, and
+    // the throughput by which the light found along it is weighted.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_material(
+        &self,
+        scatter: &Scatter,
+        depth: i32,
+        world: &impl Hittable,
+        lights: &HittableList,
+        sampler: &mut dyn Sampler,
+        features: &mut Features,
+    ) -> (Color, Color) {
+        let record = scatter.record;
+        let scattered_ray = Ray::new(
+            record.point,
+            scatter.pdf.generate(sampler),
+            scatter.ray_in.time(),
+        )
+        .with_wavelengths(scatter.ray_in.wavelengths().copied())
+        .with_absorption(scatter.ray_in.absorption().copied());
+        let pdf_value = scatter.pdf.value(scattered_ray.direction());
+        if pdf_value <= 0.0 {
+            return (Color::default(), Color::default());
+        }
+        let scattering = spectral(
+            scatter.ray_in,
+            &record.material.scattering(
+                scatter.ray_in,
+                record,
+                &scattered_ray,
+                &scatter.attenuation,
+            ),
+        );
+        if scattering == Color::default() {
+            return (Color::default(), Color::default());
+        }
+        let material_pdf = (!lights.objects.is_empty()).then_some(pdf_value);
+        let color = scattering
+            * self.ray_color(
+                &scattered_ray,
+                depth - 1,
+                world,
+                lights,
+                material_pdf,
+                sampler,
+                features,
+            )
+            / pdf_value;
+        (color, scattering / pdf_value)
+    }
+}
+
+struct Scatter<'a> {
+    ray_in: &'a Ray,
+    record: &'a HitRecord,
+    attenuation: Color,
+    pdf: &'a dyn Pdf,
+}
+
+// The values at the wavelengths of `ray` of the spectrum upsampled from the
+// linear sRGB `color`, or `color` itself for RGB rays.
+fn spectral(ray: &Ray, color: &Color) -> Color {
+    match ray.wavelengths() {
+        Some(wavelengths) => wavelengths.spectrum(color),
+        None => *color,
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf_squared = pdf * pdf;
+    pdf_squared / (pdf_squared + other_pdf * other_pdf)
+}
+
+// The tiles of a pass that are done, kept until all the tiles before them are
+// added to the checkpoint.
+struct TileMerge<'a> {
+    checkpoint: &'a mut Checkpoint,
+    next_tile: usize,
+    finished: BTreeMap<usize, (Vec<(usize, PixelSamples)>, SplatBuffer)>,
+}
+
+impl TileMerge<'_> {
+    fn take_next(&mut self) -> Option<(Vec<(usize, PixelSamples)>, SplatBuffer)> {
+        let tile = self.finished.remove(&self.next_tile)?;
+        self.next_tile += 1;
+        Some(tile)
+    }
+}
+
+fn remove_nans(color: Color) -> Color {
+    let component = |value: f64| if value.is_nan() { 0.0 } else { value };
+    color.map(component)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        aov::Aov,
+        constant_medium::ConstantMedium,
+        material::{Dielectric, DiffuseLight, Lambertian},
+        quad::Quad,
+        sampler::IndependentSampler,
+        sphere::Sphere,
+    };
+
+    fn scene() -> (HittableList, HittableList) {
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        let light = Arc::new(Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Arc::new(DiffuseLight::from_color(Color::new(4.0, 4.0, 4.0))),
+        ));
+        world.add(light.clone());
+        lights.add(light);
+        world.add(Arc::new(Sphere::stationary(
+            Point3::new(0.0, -100.5, 0.0),
+            100.0,
+            Arc::new(Lambertian::from_albedo(Color::new(0.5, 0.5, 0.5))),
+        )));
+        world.add(Arc::new(Sphere::stationary(
+            Point3::new(-0.5, 0.0, 0.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        let boundary = Arc::new(Sphere::stationary(
+            Point3::new(0.5, 0.0, 0.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        ));
+        world.add(Arc::new(ConstantMedium::from_albedo(
+            boundary,
+            2.0,
+            Color::new(0.8, 0.8, 0.8),
+        )));
+        (world, lights)
+    }
+
+    fn camera() -> Camera {
+        camera_with_samples(4)
+    }
+
+    fn camera_with_samples(samples_per_pixel: i32) -> Camera {
+        Camera::new(
+            1.0,
+            8,
+            samples_per_pixel,
+            10,
+            40.0,
+            Point3::new(0.0, 1.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.6,
+            5.0,
+            Color::new(0.2, 0.2, 0.3),
+        )
+    }
+
+    fn render_with_threads(camera: &Camera, threads: usize) -> Framebuffer {
+        let (world, lights) = scene();
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| camera.render_framebuffer(&world, &lights))
+    }
+
+    #[test]
+    fn test_render_does_not_depend_on_thread_count() {
+        let bits = |framebuffer: &Framebuffer| {
+            framebuffer
+                .pixels()
+                .iter()
+                .flat_map(|color| [color.r, color.g, color.b].map(f64::to_bits))
+                .collect::<Vec<_>>()
+        };
+        // Small tiles, whose filtered samples spill over into their neighbours.
+        let mitchell = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        for filter in [Filter::default(), mitchell] {
+            let camera = camera()
+                .with_seed(42)
+                .with_filter(filter)
+                .with_tiles(2, TileOrder::Spiral);
+            let single = render_with_threads(&camera, 1);
+            let multi = render_with_threads(&camera, 4);
+            assert_eq!(bits(&single), bits(&multi), "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn test_wide_filters_smooth_the_image() {
+        // The summed differences between horizontal neighbours.
+        let variation = |filter: Filter| {
+            let framebuffer = render_with_threads(&camera().with_filter(filter), 2);
+            let pixels = framebuffer.pixels();
+            pixels
+                .windows(2)
+                .map(|pair| pair[1].distance(&pair[0]))
+                .sum::<f64>()
+        };
+        let gaussian = Filter::Gaussian {
+            radius: 2.0,
+            sigma: 0.8,
+        };
+        assert!(variation(gaussian) < 0.5 * variation(Filter::default()));
+    }
+
+    #[test]
+    fn test_seed_changes_render() {
+        let first = render_with_threads(&camera().with_seed(1), 2);
+        let second = render_with_threads(&camera().with_seed(2), 2);
+        assert_ne!(first.pixels(), second.pixels());
+    }
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted_render() {
+        let (world, lights) = scene();
+        let camera = camera().with_seed(3).with_samples_per_pass(1);
+        let mut uninterrupted = camera.new_checkpoint(b"scene");
+        camera
+            .render_passes(&world, &lights, &mut uninterrupted, None)
+            .unwrap();
+
+        let file = std::env::temp_dir().join(format!(
+            "ray_tracer_camera_resume_{}.ckpt",
+            std::process::id()
+        ));
+        let interrupted = camera_with_samples(2).with_seed(3).with_samples_per_pass(1);
+        let mut checkpoint = interrupted.new_checkpoint(b"scene");
+        interrupted
+            .render_passes(&world, &lights, &mut checkpoint, Some(&file))
+            .unwrap();
+        let mut resumed = camera.resume_checkpoint(&file, b"scene").unwrap();
+        let other_seed = camera_with_samples(4)
+            .with_seed(4)
+            .resume_checkpoint(&file, b"scene");
+        std::fs::remove_file(&file).unwrap();
+
+        camera
+            .render_passes(&world, &lights, &mut resumed, None)
+            .unwrap();
+        assert!(resumed.sample_counts().iter().all(|count| *count == 4));
+        assert_eq!(resumed, uninterrupted);
+        assert!(matches!(
+            other_seed,
+            Err(CheckpointError::SettingsMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resuming_with_another_pass_size_matches_uninterrupted_render() {
+        let (world, lights) = scene();
+        let camera = camera().with_seed(3).with_samples_per_pass(4);
+        let mut uninterrupted = camera.new_checkpoint(b"scene");
+        camera
+            .render_passes(&world, &lights, &mut uninterrupted, None)
+            .unwrap();
+
+        let interrupted = camera_with_samples(2).with_seed(3).with_samples_per_pass(1);
+        let mut resumed = interrupted.new_checkpoint(b"scene");
+        interrupted
+            .render_passes(&world, &lights, &mut resumed, None)
+            .unwrap();
+        camera
+            .with_samples_per_pass(3)
+            .render_passes(&world, &lights, &mut resumed, None)
+            .unwrap();
+        assert_eq!(resumed.sample_counts(), uninterrupted.sample_counts());
+        // The sums of the samples are only rounded differently.
+        let (resumed, uninterrupted) = (resumed.framebuffer(), uninterrupted.framebuffer());
+        for (a, b) in resumed.pixels().iter().zip(uninterrupted.pixels()) {
+            assert!(a.distance(b) < 1e-9, "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn test_glass_absorbs_the_path_to_what_is_inside() {
+        // Glass of index 1 refracts straight through, and the light inside it
+        // is a unit of length behind its surface.
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::stationary(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            Arc::new(Dielectric::new(1.0).with_absorption(Color::new(1.0, 0.0, 2.0))),
+        )));
+        world.add(Arc::new(Quad::new(
+            Point3::new(-0.5, -0.5, -3.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(DiffuseLight::from_color(Color::new(1.0, 1.0, 1.0))),
+        )));
+        let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let color = camera().ray_color(
+            &ray,
+            10,
+            &world,
+            &HittableList::new(),
+            None,
+            &mut IndependentSampler::new(0),
+            &mut Features::default(),
+        );
+        let expected = Color::new((-1.0f64).exp(), 1.0, (-2.0f64).exp());
+        assert!(color.distance(&expected) < 1e-12, "{color:?}");
+    }
+
+    #[test]
+    fn test_spectral_render_matches_rgb_render() {
+        let mean_luminance = |camera: &Camera| {
+            let framebuffer = render_with_threads(camera, 2);
+            framebuffer
+                .pixels()
+                .iter()
+                .map(Color::luminance)
+                .sum::<f64>()
+                / framebuffer.pixels().len() as f64
+        };
+        let rgb = mean_luminance(&camera_with_samples(64).with_seed(6));
+        let spectral = mean_luminance(&camera_with_samples(64).with_seed(6).with_spectral(true));
+        assert!((spectral / rgb - 1.0).abs() < 0.05, "{rgb} {spectral}");
+    }
+
+    #[test]
+    fn test_lighting_aovs_add_up_to_the_image() {
+        let (world, lights) = scene();
+        let camera = camera().with_seed(5);
+        let mut checkpoint = camera.new_checkpoint(&[]);
+        camera
+            .render_passes(&world, &lights, &mut checkpoint, None)
+            .unwrap();
+        let framebuffer = checkpoint.framebuffer();
+        let [emission, direct, indirect, ray_depth] =
+            [Aov::Emission, Aov::Direct, Aov::Indirect, Aov::RayDepth]
+                .map(|aov| checkpoint.aov(aov));
+        for index in 0..framebuffer.pixels().len() {
+            let sum = emission.pixels()[index] + direct.pixels()[index] + indirect.pixels()[index];
+            let difference = sum.distance(&framebuffer.pixels()[index]);
+            assert!(difference < 1e-9, "{index}: {difference}");
+            assert!((0.0..=10.0).contains(&ray_depth.pixels()[index].r));
+        }
+        // The background above the scene is seen directly.
+        assert_eq!(emission.pixel(0, 0), Color::new(0.2, 0.2, 0.3));
+        assert_eq!(ray_depth.pixel(0, 0).r, 0.0);
+        assert!(indirect.pixels().iter().any(|color| color.r > 0.0));
+    }
+
+    #[test]
+    fn test_adaptive_sampling_stops_converged_pixels() {
+        let (world, lights) = scene();
+        let camera = camera()
+            .with_samples_per_pass(4)
+            .with_adaptive_sampling(AdaptiveSampling {
+                noise_threshold: 0.05,
+                max_samples_per_pixel: 32,
+            });
+        let framebuffer = camera.render_framebuffer(&world, &lights);
+        let counts = framebuffer.sample_counts();
+        assert!(counts.iter().all(|count| (4..=32).contains(count)));
+        // The background above the scene is uniform, the rest is noisy.
+        assert_eq!(framebuffer.sample_count(0, 0), 4);
+        assert!(counts.contains(&32));
+    }
 }
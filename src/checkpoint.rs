@@ -0,0 +1,404 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::aov::{Aov, FEATURE_VALUES, Features};
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+
+const MAGIC: &[u8; 8] = b"RTCKPT05";
+const HEADER_SIZE: usize = MAGIC.len() + 8 + 4 + 4;
+const PIXEL_SIZE: usize = (8 + FEATURE_VALUES) * 8 + 4;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io { file: PathBuf, source: io::Error },
+    Invalid { file: PathBuf, message: String },
+    // The checkpoint was made for another scene, camera or seed.
+    SettingsMismatch { file: PathBuf },
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io { file, source } => write!(f, "{}: {source}", file.display()),
+            CheckpointError::Invalid { file, message } => {
+                write!(f, "{}: invalid checkpoint: {message}", file.display())
+            }
+            CheckpointError::SettingsMismatch { file } => write!(
+                f,
+                "{}: checkpoint was rendered with a different scene, camera or seed",
+                file.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io { source, .. } => Some(source),
+            CheckpointError::Invalid { .. } | CheckpointError::SettingsMismatch { .. } => None,
+        }
+    }
+}
+
+// The samples taken for a pixel in one pass: their summed radiance and
+// features, the sum of the squares of their luminance, and their number.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelSamples {
+    pub radiance: Color,
+    pub features: Features,
+    pub squared_luminance: f64,
+    pub count: u32,
+}
+
+impl PixelSamples {
+    pub fn add(&mut self, radiance: Color, features: &Features) {
+        self.radiance = self.radiance + radiance;
+        self.features.accumulate(features, self.count == 0);
+        self.squared_luminance += radiance.luminance().powi(2);
+        self.count += 1;
+    }
+}
+
+// The radiance of the samples of a pass weighted by the reconstruction filter
+// at the pixel at `index`, and the sum of the weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Splat {
+    pub index: usize,
+    pub radiance: Color,
+    pub weight: f64,
+}
+
+// The state of a progressive render: the running sums of the samples taken so
+// far for each pixel, from which their mean and variance follow, and of the
+// samples splatted onto each pixel, from which its filtered value follows.
+// `settings_hash` identifies everything but the sample count that the image
+// depends on, so that a render is only resumed with the same settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    width: u32,
+    height: u32,
+    settings_hash: u64,
+    radiance: Vec<Color>,
+    squared_luminance: Vec<f64>,
+    sample_counts: Vec<u32>,
+    features: Vec<Features>,
+    filtered_radiance: Vec<Color>,
+    filter_weights: Vec<f64>,
+}
+
+impl Checkpoint {
+    pub fn new(width: u32, height: u32, settings_hash: u64) -> Self {
+        let size = width as usize * height as usize;
+        Checkpoint {
+            width,
+            height,
+            settings_hash,
+            radiance: vec![Color::default(); size],
+            squared_luminance: vec![0.0; size],
+            sample_counts: vec![0; size],
+            features: vec![Features::default(); size],
+            filtered_radiance: vec![Color::default(); size],
+            filter_weights: vec![0.0; size],
+        }
+    }
+
+    pub fn settings_hash(&self) -> u64 {
+        self.settings_hash
+    }
+
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    // Adds samples to the pixel at `index`, in row-major order.
+    pub fn add_samples(&mut self, index: usize, samples: &PixelSamples) {
+        self.radiance[index] = self.radiance[index] + samples.radiance;
+        self.squared_luminance[index] += samples.squared_luminance;
+        let first = self.sample_counts[index] == 0;
+        self.features[index].accumulate(&samples.features, first);
+        self.sample_counts[index] += samples.count;
+    }
+
+    pub fn add_splat(&mut self, splat: &Splat) {
+        let index = splat.index;
+        self.filtered_radiance[index] = self.filtered_radiance[index] + splat.radiance;
+        self.filter_weights[index] += splat.weight;
+    }
+
+    // The estimated variance of the mean luminance of the pixel at `index`,
+    // which is infinite with fewer than two samples.
+    pub fn variance(&self, index: usize) -> f64 {
+        let count = self.sample_counts[index] as f64;
+        if count < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = self.radiance[index].luminance() / count;
+        let variance =
+            (self.squared_luminance[index] / count - mean * mean).max(0.0) * count / (count - 1.0);
+        variance / count
+    }
+
+    pub fn variances(&self) -> Vec<f64> {
+        (0..self.sample_counts.len())
+            .map(|index| self.variance(index))
+            .collect()
+    }
+
+    // The standard error of the mean luminance of the pixel at `index`,
+    // relative to that mean: the noise left in the pixel. It is infinite
+    // with fewer than two samples, and zero for pixels that are uniformly
+    // black.
+    pub fn relative_error(&self, index: usize) -> f64 {
+        let variance = self.variance(index);
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let mean = self.radiance[index].luminance() / self.sample_counts[index] as f64;
+        variance.sqrt() / mean
+    }
+
+    // The filtered radiance of each pixel, black where no sample was
+    // splatted yet.
+    pub fn framebuffer(&self) -> Framebuffer {
+        let pixels = self
+            .filtered_radiance
+            .iter()
+            .zip(&self.filter_weights)
+            .map(|(radiance, weight)| {
+                if *weight == 0.0 {
+                    Color::default()
+                } else {
+                    *radiance / *weight
+                }
+            })
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels, self.sample_counts.clone())
+    }
+
+    // The value of `aov` for each pixel.
+    pub fn aov(&self, aov: Aov) -> Framebuffer {
+        let pixels = self
+            .features
+            .iter()
+            .zip(&self.sample_counts)
+            .map(|(features, count)| features.mean(aov, *count))
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels, self.sample_counts.clone())
+    }
+
+    // Reads a checkpoint written by `write`, checking that it was made with
+    // the same settings.
+    pub fn read(file: &Path, settings_hash: u64) -> Result<Self, CheckpointError> {
+        let bytes = fs::read(file).map_err(|source| CheckpointError::Io {
+            file: file.to_path_buf(),
+            source,
+        })?;
+        let invalid = |message: &str| CheckpointError::Invalid {
+            file: file.to_path_buf(),
+            message: message.to_owned(),
+        };
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(invalid("missing header"));
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        if reader.u64() != settings_hash {
+            return Err(CheckpointError::SettingsMismatch {
+                file: file.to_path_buf(),
+            });
+        }
+        let width = reader.u32();
+        let height = reader.u32();
+        let data_size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_mul(PIXEL_SIZE));
+        if data_size != Some(reader.bytes.len()) {
+            return Err(invalid("the pixel data does not match the image size"));
+        }
+        let mut checkpoint = Checkpoint::new(width, height, settings_hash);
+        for index in 0..checkpoint.sample_counts.len() {
+            checkpoint.radiance[index] = Color::new(reader.f64(), reader.f64(), reader.f64());
+            checkpoint.squared_luminance[index] = reader.f64();
+            checkpoint.sample_counts[index] = reader.u32();
+            let features = std::array::from_fn(|_| reader.f64());
+            checkpoint.features[index] = Features::from_values(&features);
+            checkpoint.filtered_radiance[index] =
+                Color::new(reader.f64(), reader.f64(), reader.f64());
+            checkpoint.filter_weights[index] = reader.f64();
+        }
+        Ok(checkpoint)
+    }
+
+    // Replaces `file` only once the new checkpoint is complete, so that an
+    // interrupted write keeps the previous one.
+    pub fn write(&self, file: &Path) -> Result<(), CheckpointError> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.radiance.len() * PIXEL_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.settings_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        for index in 0..self.sample_counts.len() {
+            let radiance = self.radiance[index];
+            for value in [
+                radiance.r,
+                radiance.g,
+                radiance.b,
+                self.squared_luminance[index],
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&self.sample_counts[index].to_le_bytes());
+            for value in self.features[index].values() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            let filtered_radiance = self.filtered_radiance[index];
+            for value in [
+                filtered_radiance.r,
+                filtered_radiance.g,
+                filtered_radiance.b,
+                self.filter_weights[index],
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let mut temporary_file = file.as_os_str().to_owned();
+        temporary_file.push(".tmp");
+        let io_error = |source| CheckpointError::Io {
+            file: file.to_path_buf(),
+            source,
+        };
+        fs::write(&temporary_file, bytes).map_err(io_error)?;
+        fs::rename(&temporary_file, file).map_err(io_error)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (value, rest) = self.bytes.split_first_chunk().unwrap();
+        self.bytes = rest;
+        *value
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    fn f64(&mut self) -> f64 {
+        f64::from_le_bytes(self.take())
+    }
+}
+
+// 64-bit FNV-1a, which unlike the standard library's hashers is stable across
+// Rust versions and platforms.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn temporary_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ray_tracer_{name}_{}.ckpt", std::process::id()))
+    }
+
+    fn samples(colors: &[Color]) -> PixelSamples {
+        let mut samples = PixelSamples::default();
+        let features = Features {
+            albedo: Color::new(0.5, 0.5, 0.5),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            material_id: 2,
+            ..Features::default()
+        };
+        colors
+            .iter()
+            .for_each(|color| samples.add(*color, &features));
+        samples
+    }
+
+    #[test]
+    fn test_framebuffer_is_the_weighted_mean_of_splats() {
+        let mut checkpoint = Checkpoint::new(2, 1, 0);
+        let color = Color::new(1.0, 2.0, 3.0);
+        checkpoint.add_samples(0, &samples(&[color, color]));
+        checkpoint.add_samples(0, &samples(&[color]));
+        checkpoint.add_splat(&Splat {
+            index: 0,
+            radiance: color * 1.5,
+            weight: 1.5,
+        });
+        checkpoint.add_splat(&Splat {
+            index: 0,
+            radiance: Color::new(3.0, 3.0, 3.0) * 0.5,
+            weight: 0.5,
+        });
+        let framebuffer = checkpoint.framebuffer();
+        assert_eq!(framebuffer.pixel(0, 0), Color::new(1.5, 2.25, 3.0));
+        assert_eq!(framebuffer.sample_count(0, 0), 3);
+        assert_eq!(framebuffer.pixel(1, 0), Color::default());
+    }
+
+    #[test]
+    fn test_relative_error() {
+        let mut checkpoint = Checkpoint::new(3, 1, 0);
+        let grey = |value| Color::new(value, value, value);
+        checkpoint.add_samples(0, &samples(&[grey(1.0)]));
+        assert_eq!(checkpoint.relative_error(0), f64::INFINITY);
+        checkpoint.add_samples(1, &samples(&[grey(0.0); 4]));
+        assert_eq!(checkpoint.relative_error(1), 0.0);
+        // A mean of 2 with a sample variance of 4/3, over 4 samples.
+        checkpoint.add_samples(2, &samples(&[grey(1.0), grey(3.0), grey(1.0), grey(3.0)]));
+        let expected = (4.0 / 3.0 / 4.0_f64).sqrt() / 2.0;
+        assert!((checkpoint.relative_error(2) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let file = temporary_file("checkpoint_round_trip");
+        let mut checkpoint = Checkpoint::new(3, 2, 42);
+        checkpoint.add_samples(4, &samples(&[Color::new(0.1, 0.2, 0.3)]));
+        checkpoint.add_splat(&Splat {
+            index: 3,
+            radiance: Color::new(0.01, 0.02, 0.03),
+            weight: 0.1,
+        });
+        checkpoint.write(&file).unwrap();
+        let read = Checkpoint::read(&file, 42);
+        let mismatch = Checkpoint::read(&file, 43);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(read.unwrap(), checkpoint);
+        assert!(matches!(
+            mismatch,
+            Err(CheckpointError::SettingsMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_checkpoint_is_invalid() {
+        let file = temporary_file("checkpoint_truncated");
+        Checkpoint::new(3, 2, 42).write(&file).unwrap();
+        let bytes = fs::read(&file).unwrap();
+        fs::write(&file, &bytes[..bytes.len() - 1]).unwrap();
+        let result = Checkpoint::read(&file, 42);
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(result, Err(CheckpointError::Invalid { .. })));
+    }
+}
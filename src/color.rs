@@ -1,27 +1,203 @@
-use crate::interval::Interval;
-use crate::vec3::Vec3;
+use std::{
+    iter::Sum,
+    ops::{Add, Div, Mul, Sub},
+};
 
-pub type Color = Vec3;
+use serde::{Deserialize, Serialize};
 
-pub fn format_color(pixel: &Color) -> String {
-    let mut r = pixel.x;
-    let mut g = pixel.y;
-    let mut b = pixel.z;
+use crate::{sampler::Sampler, vec3::Vec3};
+
+// A colour in linear sRGB, with the Rec. 709 primaries and the D65 white
+// point, unless a conversion says otherwise. Scene files may also name its
+// channels `x`, `y` and `z`, as they did when colours were vectors.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Color {
+    #[serde(alias = "x")]
+    pub r: f64,
+    #[serde(alias = "y")]
+    pub g: f64,
+    #[serde(alias = "z")]
+    pub b: f64,
+}
+
+impl Color {
+    pub fn new(r: f64, g: f64, b: f64) -> Self {
+        Color { r, g, b }
+    }
+
+    pub fn grey(value: f64) -> Self {
+        Color::new(value, value, value)
+    }
+
+    pub fn random(sampler: &mut dyn Sampler) -> Self {
+        Color::new(sampler.random(), sampler.random(), sampler.random())
+    }
+
+    pub fn max(&self, other: &Color) -> Color {
+        Color::new(
+            self.r.max(other.r),
+            self.g.max(other.g),
+            self.b.max(other.b),
+        )
+    }
+
+    pub fn min(&self, other: &Color) -> Color {
+        Color::new(
+            self.r.min(other.r),
+            self.g.min(other.g),
+            self.b.min(other.b),
+        )
+    }
+
+    // The Euclidean distance between two colours in RGB space.
+    pub fn distance(&self, other: &Color) -> f64 {
+        let difference = *self - *other;
+        (difference.r * difference.r + difference.g * difference.g + difference.b * difference.b)
+            .sqrt()
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    // Relative luminance with the Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // Decodes a colour stored with the sRGB transfer function, like the texels
+    // of 8-bit images.
+    pub fn from_srgb(encoded: &Color) -> Color {
+        encoded.map(srgb_to_linear)
+    }
+
+    pub fn to_srgb(&self) -> Color {
+        self.map(linear_to_srgb)
+    }
+
+    // ACEScg has the wider AP1 primaries and the D60 white point of ACES; the
+    // matrices include a Bradford adaptation between the white points.
+    pub fn from_acescg(acescg: &Color) -> Color {
+        Color::new(
+            1.705_050_993 * acescg.r - 0.621_792_121 * acescg.g - 0.083_258_872 * acescg.b,
+            -0.130_256_418 * acescg.r + 1.140_804_737 * acescg.g - 0.010_548_319 * acescg.b,
+            -0.024_003_357 * acescg.r - 0.128_968_976 * acescg.g + 1.152_972_333 * acescg.b,
+        )
+    }
+
+    pub fn to_acescg(&self) -> Color {
+        Color::new(
+            0.613_097_402 * self.r + 0.339_523_146 * self.g + 0.047_379_451 * self.b,
+            0.070_193_722 * self.r + 0.916_353_879 * self.g + 0.013_452_398 * self.b,
+            0.020_615_593 * self.r + 0.109_569_773 * self.g + 0.869_814_634 * self.b,
+        )
+    }
+}
+
+// For the AOVs that store vectors, like normals, in images.
+impl From<Vec3> for Color {
+    fn from(vector: Vec3) -> Color {
+        Color::new(vector.x, vector.y, vector.z)
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, other: Color) -> Color {
+        Color::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+
+    fn sub(self, other: Color) -> Color {
+        Color::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
+impl Mul<Color> for f64 {
+    type Output = Color;
+
+    fn mul(self, color: Color) -> Color {
+        Color::new(self * color.r, self * color.g, self * color.b)
+    }
+}
+
+impl Mul<f64> for Color {
+    type Output = Color;
+
+    fn mul(self, scalar: f64) -> Color {
+        Color::new(self.r * scalar, self.g * scalar, self.b * scalar)
+    }
+}
 
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+impl Mul<Color> for Color {
+    type Output = Color;
 
-    let intensity = Interval::new(0.000, 0.999);
-    let rbyte = (255.999 * intensity.clamp(r)) as i16;
-    let gbyte = (255.999 * intensity.clamp(g)) as i16;
-    let bbyte = (255.999 * intensity.clamp(b)) as i16;
+    fn mul(self, other: Color) -> Color {
+        Color::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    }
+}
+
+impl Div<f64> for Color {
+    type Output = Color;
+
+    fn div(self, scalar: f64) -> Color {
+        Color::new(self.r / scalar, self.g / scalar, self.b / scalar)
+    }
+}
 
+impl Div<Color> for Color {
+    type Output = Color;
+
+    fn div(self, other: Color) -> Color {
+        Color::new(self.r / other.r, self.g / other.g, self.b / other.b)
+    }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color {
+        iter.fold(Color::default(), |acc, color| acc + color)
+    }
+}
+
+pub fn format_color(pixel: &Color) -> String {
+    let [rbyte, gbyte, bbyte] = color_to_bytes(pixel);
     format!("{rbyte} {gbyte} {bbyte}")
 }
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    f64::max(linear_component, 0.0).sqrt()
+// Encodes a linear colour with the sRGB transfer function and quantizes it to
+// 8 bits, clamping it to [0, 1].
+pub fn color_to_bytes(pixel: &Color) -> [u8; 3] {
+    [pixel.r, pixel.g, pixel.b].map(|component| quantize(linear_to_srgb(component), 0.0))
+}
+
+// The sRGB transfer function: linear near black, then a 1/2.4 power.
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// The inverse of `linear_to_srgb`.
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Rounds an encoded value in [0, 1] to 8 bits, after adding `dither` in units
+// of the last bit.
+pub fn quantize(encoded: f64, dither: f64) -> u8 {
+    (255.0 * encoded.clamp(0.0, 1.0) + dither)
+        .round()
+        .clamp(0.0, 255.0) as u8
 }
 
 #[cfg(test)]
@@ -32,7 +208,57 @@ mod tests {
     fn test_write_color() {
         let color = Color::new(0.5, 0.7, 0.9);
         format_color(&color);
-        let expected = "127 179 230\n";
+        let expected = "188 218 243";
         assert_eq!(format_color(&color), expected)
     }
+
+    #[test]
+    fn test_color_to_bytes_clamps() {
+        let color = Color::new(-1.0, 0.25, 4.0);
+        assert_eq!(color_to_bytes(&color), [0, 137, 255]);
+    }
+
+    #[test]
+    fn test_linear_to_srgb() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 1e-6);
+        // The two pieces meet at the threshold.
+        let below = linear_to_srgb(0.003_130_8);
+        let above = linear_to_srgb(0.003_130_8 + 1e-12);
+        assert!((above - below).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for value in [0.0, 0.002, 0.04, 0.2, 0.5, 1.0] {
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-12);
+        }
+        let color = Color::new(0.1, 0.5, 0.9);
+        assert!(Color::from_srgb(&color.to_srgb()).distance(&color) < 1e-12);
+        // Mid-grey texels are darker once linear.
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_acescg_round_trip() {
+        let color = Color::new(0.8, 0.3, 0.1);
+        assert!(Color::from_acescg(&color.to_acescg()).distance(&color) < 1e-8);
+        // White maps to white, with the white points adapted.
+        let white = Color::grey(1.0).to_acescg();
+        assert!(white.distance(&Color::grey(1.0)) < 1e-8, "{white:?}");
+    }
+
+    #[test]
+    fn test_luminance_and_arithmetic() {
+        assert!((Color::grey(0.5).luminance() - 0.5).abs() < 1e-12);
+        let color = 2.0 * Color::new(0.1, 0.2, 0.3) * Color::new(1.0, 0.5, 2.0) - Color::grey(0.1);
+        assert!(color.distance(&Color::new(0.1, 0.1, 1.1)) < 1e-12);
+        assert_eq!(
+            [Color::new(1.0, 0.0, 0.0), Color::new(0.0, 2.0, 3.0)]
+                .into_iter()
+                .sum::<Color>(),
+            Color::new(1.0, 2.0, 3.0)
+        );
+    }
 }
@@ -7,6 +7,7 @@ use crate::{
     interval::Interval,
     material::{Isotropic, Material},
     ray::Ray,
+    sampler::Sampler,
     texture::Texture,
     vec3::Vec3,
 };
@@ -41,20 +42,22 @@ impl ConstantMedium {
 
 impl Hittable for ConstantMedium {
     fn bounding_box(&self) -> &crate::aabb::AABB {
-        &self.boundary.bounding_box()
+        self.boundary.bounding_box()
     }
 
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
-        let mut record1 = self.boundary.hit(ray, &Interval::universe())?;
-        let mut record2 = self
-            .boundary
-            .hit(ray, &Interval::new(record1.t + 1e-4, f64::INFINITY))?;
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut record1 = self.boundary.hit(ray, &Interval::universe(), sampler)?;
+        let mut record2 = self.boundary.hit(
+            ray,
+            &Interval::new(record1.t + 1e-4, f64::INFINITY),
+            sampler,
+        )?;
         record1.t = f64::max(record1.t, interval.min);
         record2.t = f64::min(record2.t, interval.max);
         record1.t = f64::max(record1.t, 0.0);
         let ray_length = ray.direction().length();
         let distance_inside_boundary = (record2.t - record1.t) * ray_length;
-        let hit_distance = self.negative_inverse_density * rand::random::<f64>().ln();
+        let hit_distance = self.negative_inverse_density * (1.0 - sampler.random()).ln();
         if hit_distance > distance_inside_boundary {
             return None;
         }
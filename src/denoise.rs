@@ -0,0 +1,263 @@
+use crate::{color::Color, framebuffer::Framebuffer};
+
+// The B3 spline, whose dilations make up the à-trous wavelet.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Keeps pixels without reflectance, like a black background, from dividing by
+// zero when the texture is taken out of the colour.
+const ALBEDO_EPSILON: f64 = 1e-3;
+
+// An edge-avoiding à-trous filter, after Dammertz et al. and SVGF: a 5x5
+// kernel is applied with holes that double with each iteration, and the weight
+// of each neighbour drops with how much its normal, albedo and luminance differ
+// from the pixel's. The colour is divided by the albedo first, so that only the
+// lighting is blurred and textures stay sharp. The luminance tolerance follows
+// the estimated variance around each pixel, which the filter reduces as it
+// goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    pub iterations: u32,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+    // Luminance differences are tolerated up to this many standard deviations.
+    pub luminance_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            normal_sigma: 0.2,
+            albedo_sigma: 0.1,
+            luminance_sigma: 4.0,
+        }
+    }
+}
+
+// What the denoiser knows of a pixel in the current iteration.
+#[derive(Clone, Copy)]
+struct Pixel {
+    illumination: Color,
+    variance: f64,
+}
+
+impl Denoiser {
+    // `variance` is the variance of the mean luminance of each pixel of
+    // `color`, and may be infinite where it is unknown, in which case only the
+    // albedo and the normals guide the filter.
+    pub fn denoise(
+        &self,
+        color: &Framebuffer,
+        albedo: &Framebuffer,
+        normal: &Framebuffer,
+        variance: &[f64],
+    ) -> Framebuffer {
+        let (width, height) = (color.width() as usize, color.height() as usize);
+        let albedos = albedo.pixels();
+        let normals = normal.pixels();
+        let mut pixels = color
+            .pixels()
+            .iter()
+            .zip(albedos)
+            .zip(variance)
+            .map(|((color, albedo), variance)| {
+                let albedo = *albedo + Color::new(1.0, 1.0, 1.0) * ALBEDO_EPSILON;
+                Pixel {
+                    illumination: divide(color, &albedo),
+                    variance: variance / albedo.luminance().max(ALBEDO_EPSILON).powi(2),
+                }
+            })
+            .collect::<Vec<Pixel>>();
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let variances = blur_variance(&pixels, width, height);
+            pixels = (0..width * height)
+                .map(|index| {
+                    let (x, y) = (index % width, index / width);
+                    let center = &pixels[index];
+                    let tolerance = self.luminance_sigma * variances[index].sqrt() + 1e-10;
+                    let center_luminance = center.illumination.luminance();
+                    let mut illumination = Color::default();
+                    let mut variance = 0.0;
+                    let mut total = 0.0;
+                    for (dy, row_weight) in KERNEL.iter().enumerate() {
+                        let ny = y as i64 + (dy as i64 - 2) * step;
+                        if !(0..height as i64).contains(&ny) {
+                            continue;
+                        }
+                        for (dx, column_weight) in KERNEL.iter().enumerate() {
+                            let nx = x as i64 + (dx as i64 - 2) * step;
+                            if !(0..width as i64).contains(&nx) {
+                                continue;
+                            }
+                            let neighbour_index = ny as usize * width + nx as usize;
+                            let neighbour = &pixels[neighbour_index];
+                            let luminance_distance =
+                                (neighbour.illumination.luminance() - center_luminance).abs();
+                            let weight = row_weight
+                                * column_weight
+                                * gaussian(
+                                    &normals[index],
+                                    &normals[neighbour_index],
+                                    self.normal_sigma,
+                                )
+                                * gaussian(
+                                    &albedos[index],
+                                    &albedos[neighbour_index],
+                                    self.albedo_sigma,
+                                )
+                                * (-luminance_distance / tolerance).exp();
+                            if weight == 0.0 {
+                                continue;
+                            }
+                            illumination = illumination + weight * neighbour.illumination;
+                            variance += weight * weight * neighbour.variance;
+                            total += weight;
+                        }
+                    }
+                    Pixel {
+                        illumination: illumination / total,
+                        variance: variance / (total * total),
+                    }
+                })
+                .collect();
+        }
+        let pixels = pixels
+            .iter()
+            .zip(albedos)
+            .map(|(pixel, albedo)| {
+                pixel.illumination * (*albedo + Color::new(1.0, 1.0, 1.0) * ALBEDO_EPSILON)
+            })
+            .collect();
+        Framebuffer::from_pixels(
+            color.width(),
+            color.height(),
+            pixels,
+            color.sample_counts().to_vec(),
+        )
+    }
+}
+
+// The variances of the pixels blurred over their 3x3 neighbourhood, which are
+// themselves noisy estimates: a pixel whose few samples happen to agree would
+// otherwise reject all of its neighbours.
+fn blur_variance(pixels: &[Pixel], width: usize, height: usize) -> Vec<f64> {
+    let kernel = [0.25, 0.5, 0.25];
+    (0..width * height)
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let mut variance = 0.0;
+            let mut total = 0.0;
+            for (dy, row_weight) in kernel.iter().enumerate() {
+                for (dx, column_weight) in kernel.iter().enumerate() {
+                    let (nx, ny) = ((x + dx).wrapping_sub(1), (y + dy).wrapping_sub(1));
+                    if nx < width && ny < height {
+                        let weight = row_weight * column_weight;
+                        variance += weight * pixels[ny * width + nx].variance;
+                        total += weight;
+                    }
+                }
+            }
+            variance / total
+        })
+        .collect()
+}
+
+fn divide(color: &Color, albedo: &Color) -> Color {
+    Color::new(color.r / albedo.r, color.g / albedo.g, color.b / albedo.b)
+}
+
+fn gaussian(a: &Color, b: &Color, sigma: f64) -> f64 {
+    let difference = *a - *b;
+    let squared_distance =
+        difference.r * difference.r + difference.g * difference.g + difference.b * difference.b;
+    (-squared_distance / (2.0 * sigma * sigma)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sampler::{IndependentSampler, Sampler},
+        vec3::Vec3,
+    };
+
+    const SIZE: u32 = 32;
+
+    // A wall whose left half is red and right half is white, lit evenly but
+    // rendered with noise, and the variance of each pixel.
+    fn noisy_wall(noise: f64) -> (Framebuffer, Framebuffer, Framebuffer, Vec<f64>) {
+        let mut sampler = IndependentSampler::new(7);
+        let size = (SIZE * SIZE) as usize;
+        let albedos = (0..size)
+            .map(|index| match index as u32 % SIZE < SIZE / 2 {
+                true => Color::new(0.8, 0.1, 0.1),
+                false => Color::new(0.9, 0.9, 0.9),
+            })
+            .collect::<Vec<Color>>();
+        let colors = albedos
+            .iter()
+            .map(|albedo| *albedo * (1.0 + noise * (2.0 * sampler.random() - 1.0)))
+            .collect::<Vec<Color>>();
+        let variances = albedos
+            .iter()
+            .map(|albedo| (albedo.luminance() * noise).powi(2) / 3.0)
+            .collect();
+        let framebuffer = |pixels| Framebuffer::from_pixels(SIZE, SIZE, pixels, vec![1; size]);
+        (
+            framebuffer(colors),
+            framebuffer(albedos),
+            framebuffer(vec![Color::new(0.0, 0.0, 1.0); size]),
+            variances,
+        )
+    }
+
+    // The mean squared difference from the noiseless wall, in columns `x0..x1`.
+    fn error(image: &Framebuffer, albedo: &Framebuffer, x0: u32, x1: u32) -> f64 {
+        let pixels = (0..SIZE).flat_map(|y| (x0..x1).map(move |x| (x, y)));
+        pixels
+            .map(|(x, y)| image.pixel(x, y).distance(&albedo.pixel(x, y)).powi(2))
+            .sum::<f64>()
+            / ((x1 - x0) * SIZE) as f64
+    }
+
+    #[test]
+    fn test_denoising_removes_noise_and_keeps_edges() {
+        let (color, albedo, normal, variance) = noisy_wall(0.5);
+        let denoised = Denoiser::default().denoise(&color, &albedo, &normal, &variance);
+        let before = error(&color, &albedo, 0, SIZE);
+        let after = error(&denoised, &albedo, 0, SIZE);
+        assert!(after < before / 10.0, "{before} {after}");
+        // The columns on either side of the edge do not bleed into each other.
+        let edge = error(&denoised, &albedo, SIZE / 2 - 1, SIZE / 2 + 1);
+        assert!(edge < before / 10.0, "{before} {edge}");
+    }
+
+    #[test]
+    fn test_normals_stop_the_filter() {
+        let grey = Framebuffer::from_pixels(
+            SIZE,
+            SIZE,
+            vec![Color::new(0.5, 0.5, 0.5); (SIZE * SIZE) as usize],
+            vec![1; (SIZE * SIZE) as usize],
+        );
+        // Two walls at a right angle, one lit and one dark, with the same
+        // albedo and an unknown variance.
+        let mut color = grey.clone();
+        let mut normal = grey.clone();
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (radiance, direction) = match y < SIZE / 2 {
+                    true => (Color::new(1.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+                    false => (Color::default(), Vec3::new(0.0, 0.0, 1.0)),
+                };
+                color.set_pixel(x, y, radiance, 1);
+                normal.set_pixel(x, y, Color::from(direction), 1);
+            }
+        }
+        let variance = vec![f64::INFINITY; (SIZE * SIZE) as usize];
+        let denoised = Denoiser::default().denoise(&color, &grey, &normal, &variance);
+        assert!(denoised.pixel(3, SIZE / 2 - 1).r > 0.99);
+        assert!(denoised.pixel(3, SIZE / 2).r < 0.01);
+    }
+}
@@ -0,0 +1,308 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{checkpoint::Splat, color::Color, tile::Tile};
+
+// The reconstruction filter with which the samples are splatted onto the
+// pixels around them: each pixel is the weighted mean of the samples within
+// `radius` of its centre, in pixels, along both axes. Mitchell and Lanczos
+// have negative lobes, which sharpen edges at the cost of slight ringing.
+// Radii below half a pixel are rejected, as they would leave pixels without
+// any samples.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    deny_unknown_fields,
+    try_from = "UncheckedFilter"
+)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    // Mitchell and Netravali's cubic, stretched from its usual radius of 2.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // A sinc windowed by a sinc stretched over the radius.
+    Lanczos { radius: f64 },
+}
+
+// A filter as written in the camera settings, before its parameters are
+// checked.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum UncheckedFilter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    Mitchell { radius: f64, b: f64, c: f64 },
+    Lanczos { radius: f64 },
+}
+
+impl TryFrom<UncheckedFilter> for Filter {
+    type Error = String;
+
+    fn try_from(unchecked: UncheckedFilter) -> Result<Self, Self::Error> {
+        let filter = match unchecked {
+            UncheckedFilter::Box { radius } => Filter::Box { radius },
+            UncheckedFilter::Tent { radius } => Filter::Tent { radius },
+            UncheckedFilter::Gaussian { radius, sigma } => {
+                if sigma.is_nan() || sigma <= 0.0 {
+                    return Err(format!("sigma must be positive, got {sigma}"));
+                }
+                Filter::Gaussian { radius, sigma }
+            }
+            UncheckedFilter::Mitchell { radius, b, c } => Filter::Mitchell { radius, b, c },
+            UncheckedFilter::Lanczos { radius } => Filter::Lanczos { radius },
+        };
+        let radius = filter.radius();
+        if radius.is_nan() || radius < 0.5 {
+            return Err(format!("radius must be at least 0.5 pixels, got {radius}"));
+        }
+        Ok(filter)
+    }
+}
+
+// The box over the pixel's own footprint, which averages the samples of each
+// pixel.
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    // The weight along one axis of a sample at `offset` from a pixel centre;
+    // the filters are separable.
+    pub fn weight(&self, offset: f64) -> f64 {
+        let x = offset.abs();
+        if x >= self.radius() {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => 1.0 - x / radius,
+            // Shifted down to reach zero at the radius.
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x.powi(2)
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { radius } => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+// Accumulates the splats of the samples taken in a tile onto the pixels
+// within the filter radius of them, which extend past the tile.
+pub struct SplatBuffer {
+    filter: Filter,
+    image_width: u32,
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+    radiance: Vec<Color>,
+    weights: Vec<f64>,
+    column_weights: Vec<f64>,
+}
+
+impl SplatBuffer {
+    pub fn new(filter: Filter, tile: &Tile, image_width: u32, image_height: u32) -> Self {
+        // Samples lie up to half a pixel from the centre of their pixel.
+        let margin = (filter.radius() + 0.5).ceil() as u32;
+        let (x0, y0) = (
+            tile.x0.saturating_sub(margin),
+            tile.y0.saturating_sub(margin),
+        );
+        let width = (tile.x1 + margin).min(image_width) - x0;
+        let height = (tile.y1 + margin).min(image_height) - y0;
+        let size = width as usize * height as usize;
+        SplatBuffer {
+            filter,
+            image_width,
+            x0,
+            y0,
+            width,
+            height,
+            radiance: vec![Color::default(); size],
+            weights: vec![0.0; size],
+            column_weights: Vec::new(),
+        }
+    }
+
+    // Splats a sample taken at (`x`, `y`), in pixels from the centre of the
+    // top-left pixel.
+    pub fn add(&mut self, x: f64, y: f64, radiance: Color) {
+        let radius = self.filter.radius();
+        let range = |position: f64, start: u32, len: u32| {
+            let first = ((position - radius).ceil() as i64).max(start as i64);
+            let last = ((position + radius).floor() as i64).min((start + len) as i64 - 1);
+            first..=last
+        };
+        let columns = range(x, self.x0, self.width);
+        self.column_weights.clear();
+        for column in columns.clone() {
+            self.column_weights
+                .push(self.filter.weight(column as f64 - x));
+        }
+        for row in range(y, self.y0, self.height) {
+            let row_weight = self.filter.weight(row as f64 - y);
+            if row_weight == 0.0 {
+                continue;
+            }
+            let row_start = (row - self.y0 as i64) * self.width as i64 - self.x0 as i64;
+            for (column, column_weight) in columns.clone().zip(&self.column_weights) {
+                if *column_weight == 0.0 {
+                    continue;
+                }
+                let weight = row_weight * column_weight;
+                let index = (row_start + column) as usize;
+                self.radiance[index] = self.radiance[index] + weight * radiance;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    pub fn splats(&self) -> impl Iterator<Item = Splat> + '_ {
+        (0..self.radiance.len()).map(|index| {
+            let x = self.x0 + index as u32 % self.width;
+            let y = self.y0 + index as u32 / self.width;
+            Splat {
+                index: (y * self.image_width + x) as usize,
+                radiance: self.radiance[index],
+                weight: self.weights[index],
+            }
+        })
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sum of the weights of the pixels around a sample, for samples across
+    // a pixel.
+    fn weight_sums(filter: Filter) -> Vec<f64> {
+        (0..10)
+            .map(|i| {
+                let offset = i as f64 / 10.0 - 0.5;
+                (-4..=4).map(|k| filter.weight(k as f64 - offset)).sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_weights() {
+        let filters = [
+            Filter::default(),
+            Filter::Tent { radius: 1.5 },
+            Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            Filter::Lanczos { radius: 3.0 },
+        ];
+        for filter in filters {
+            assert!(filter.weight(0.0) > 0.0, "{filter:?}");
+            assert!(filter.weight(0.0) >= filter.weight(0.3), "{filter:?}");
+            assert_eq!(filter.weight(filter.radius()), 0.0, "{filter:?}");
+            assert_eq!(filter.weight(-0.3), filter.weight(0.3), "{filter:?}");
+        }
+        assert_eq!(Filter::default().weight(0.49), 1.0);
+        assert!((Filter::Tent { radius: 2.0 }.weight(0.5) - 0.75).abs() < 1e-12);
+        let mitchell = Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        assert!(mitchell.weight(1.5) < 0.0);
+    }
+
+    #[test]
+    fn test_settings_with_pixels_left_empty_are_rejected() {
+        let parse = |json: &str| serde_json::from_str::<Filter>(json).map_err(|e| e.to_string());
+        assert_eq!(
+            parse(r#"{ "type": "gaussian", "radius": 1.5, "sigma": 0.5 }"#),
+            Ok(Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5
+            })
+        );
+        for (json, message) in [
+            (
+                r#"{ "type": "box", "radius": 0.3 }"#,
+                "radius must be at least 0.5",
+            ),
+            (
+                r#"{ "type": "tent", "radius": -1.0 }"#,
+                "radius must be at least 0.5",
+            ),
+            (
+                r#"{ "type": "gaussian", "radius": 1.5, "sigma": 0.0 }"#,
+                "sigma must be positive",
+            ),
+            (
+                r#"{ "type": "box", "radius": 1.0, "sigma": 1.0 }"#,
+                "unknown field",
+            ),
+        ] {
+            let error = parse(json).unwrap_err();
+            assert!(error.contains(message), "{json}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_filters_that_keep_flat_images_flat() {
+        // Samples anywhere get the same total weight from a tent of radius 1
+        // and a Mitchell filter of radius 2, which thus need no normalisation.
+        for filter in [
+            Filter::Tent { radius: 1.0 },
+            Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+        ] {
+            for sum in weight_sums(filter) {
+                assert!((sum - 1.0).abs() < 1e-12, "{filter:?} {sum}");
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::{SplitStrategy, median_partition, sah_partition},
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Deeper nodes are made leaves, which bounds the traversal stack.
+const MAX_DEPTH: usize = 64;
+
+enum NodeKind {
+    // The first child directly follows its parent in `nodes`.
+    Interior { second_child: usize, axis: u8 },
+    Leaf { first: usize, count: usize },
+}
+
+struct FlatNode {
+    bbox: AABB,
+    kind: NodeKind,
+}
+
+// A bounding volume hierarchy stored as a contiguous array of nodes in depth
+// first order, with index-based children and leaves that refer to ranges of
+// the reordered objects. Rays visit the child on their side of the split axis
+// first, so that the other one can often be skipped.
+pub struct FlatBVH {
+    nodes: Vec<FlatNode>,
+    objects: Vec<Arc<dyn Hittable>>,
+}
+
+impl FlatBVH {
+    pub fn new(world: &mut HittableList) -> Self {
+        FlatBVH::with_strategy(world, SplitStrategy::Median)
+    }
+
+    pub fn with_strategy(world: &mut HittableList, strategy: SplitStrategy) -> Self {
+        let mut nodes = Vec::with_capacity(2 * world.objects.len());
+        FlatBVH::build(&mut nodes, &mut world.objects, 0, 0, strategy);
+        FlatBVH {
+            nodes,
+            objects: world.objects.clone(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn build(
+        nodes: &mut Vec<FlatNode>,
+        objects: &mut [Arc<dyn Hittable>],
+        first: usize,
+        depth: usize,
+        strategy: SplitStrategy,
+    ) -> usize {
+        let bbox = objects.iter().fold(AABB::empty(), |bbox, object| {
+            bbox.union(object.bounding_box())
+        });
+        let split = match strategy {
+            _ if depth + 1 >= MAX_DEPTH => None,
+            SplitStrategy::Median if objects.len() <= 1 => None,
+            SplitStrategy::Median => Some(median_partition(objects, &bbox)),
+            SplitStrategy::Sah {
+                bins,
+                max_leaf_size,
+            } => sah_partition(objects, &bbox, bins, max_leaf_size),
+        };
+
+        let index = nodes.len();
+        let count = objects.len();
+        nodes.push(FlatNode {
+            bbox,
+            kind: NodeKind::Leaf { first, count },
+        });
+        if let Some((mid, axis)) = split {
+            let (left, right) = objects.split_at_mut(mid);
+            FlatBVH::build(nodes, left, first, depth + 1, strategy);
+            let second_child = FlatBVH::build(nodes, right, first + mid, depth + 1, strategy);
+            nodes[index].kind = NodeKind::Interior { second_child, axis };
+        }
+        index
+    }
+}
+
+impl Hittable for FlatBVH {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        let direction = ray.direction();
+        let inverse_direction = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let direction_is_negative = [direction.x < 0.0, direction.y < 0.0, direction.z < 0.0];
+
+        let mut closest = None;
+        let mut interval = *interval;
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            match node.kind {
+                // Single objects are tested directly, as their box would cost
+                // about as much as the object itself.
+                NodeKind::Leaf { first, count: 1 } => {
+                    if let Some(record) = self.objects[first].hit(ray, &interval, sampler) {
+                        interval.max = record.t;
+                        closest = Some(record);
+                    }
+                }
+                _ if !node
+                    .bbox
+                    .hit_inverse(ray.origin(), &inverse_direction, &interval) => {}
+                NodeKind::Leaf { first, count } => {
+                    for object in &self.objects[first..first + count] {
+                        if let Some(record) = object.hit(ray, &interval, sampler) {
+                            interval.max = record.t;
+                            closest = Some(record);
+                        }
+                    }
+                }
+                NodeKind::Interior { second_child, axis } => {
+                    let (near, far) = if direction_is_negative[axis as usize] {
+                        (second_child, index + 1)
+                    } else {
+                        (index + 1, second_child)
+                    };
+                    stack[stack_size] = far;
+                    stack_size += 1;
+                    index = near;
+                    continue;
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            index = stack[stack_size];
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.nodes[0].bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh::BVHNode, color::Color, material::Lambertian, sampler::IndependentSampler,
+        sphere::Sphere, vec3::Point3,
+    };
+
+    fn spheres() -> HittableList {
+        let material = Arc::new(Lambertian::from_albedo(Color::default()));
+        let mut list = HittableList::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Point3::new(i as f64, j as f64, ((i * 7 + j * 3) % 5) as f64);
+                list.add(Arc::new(Sphere::stationary(center, 0.4, material.clone())));
+            }
+        }
+        list
+    }
+
+    #[test]
+    fn test_same_hits_as_tree() {
+        let mut sampler = IndependentSampler::new(0);
+        let tree = BVHNode::new(&mut spheres());
+        let interval = Interval::new(0.001, f64::INFINITY);
+        for strategy in [SplitStrategy::Median, SplitStrategy::sah(4)] {
+            let flat = FlatBVH::with_strategy(&mut spheres(), strategy);
+            for k in 0..200 {
+                let origin = Point3::new(4.5, 4.5, 20.0);
+                let target = Point3::new((k % 20) as f64 * 0.5, (k / 20) as f64, 0.0);
+                for ray in [
+                    Ray::new(origin, target - origin, 0.0),
+                    Ray::new(target, origin - target, 0.0),
+                    Ray::new(
+                        target - Vec3::new(0.0, 0.0, 10.0),
+                        Vec3::new(1.0, 0.1, 1.0),
+                        0.0,
+                    ),
+                ] {
+                    let expected = tree
+                        .hit(&ray, &interval, &mut sampler)
+                        .map(|record| record.t);
+                    let actual = flat
+                        .hit(&ray, &interval, &mut sampler)
+                        .map(|record| record.t);
+                    assert_eq!(actual, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let flat = FlatBVH::new(&mut spheres());
+        assert_eq!(flat.node_count(), 199);
+        let bbox = flat.bounding_box();
+        assert!((bbox.x.min + 0.4).abs() < 1e-9 && (bbox.x.max - 9.4).abs() < 1e-9);
+        let leaves = flat
+            .nodes
+            .iter()
+            .filter_map(|node| match node.kind {
+                NodeKind::Leaf { first, count } => Some((first, count)),
+                NodeKind::Interior { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(leaves.len(), 100);
+        assert!(leaves.iter().enumerate().all(|(i, leaf)| *leaf == (i, 1)));
+    }
+
+    #[test]
+    fn test_empty_bvh_is_never_hit() {
+        let mut sampler = IndependentSampler::new(0);
+        let flat = FlatBVH::new(&mut HittableList::new());
+        let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(
+            flat.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::color::Color;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    sample_counts: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = width as usize * height as usize;
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color::default(); size],
+            sample_counts: vec![0; size],
+        }
+    }
+
+    pub fn from_pixels(
+        width: u32,
+        height: u32,
+        pixels: Vec<Color>,
+        sample_counts: Vec<u32>,
+    ) -> Self {
+        let size = width as usize * height as usize;
+        assert_eq!(pixels.len(), size, "Expected {size} pixels");
+        assert_eq!(sample_counts.len(), size, "Expected {size} sample counts");
+        Framebuffer {
+            width,
+            height,
+            pixels,
+            sample_counts,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.sample_counts[self.index(x, y)]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color, sample_count: u32) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+        self.sample_counts[index] = sample_count;
+    }
+
+    // Linear colours in row-major order, starting from the top-left pixel.
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn sample_counts(&self) -> &[u32] {
+        &self.sample_counts
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({x}, {y}) is outside of a {}x{} framebuffer",
+            self.width,
+            self.height
+        );
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_black_without_samples() {
+        let framebuffer = Framebuffer::new(3, 2);
+        assert_eq!(framebuffer.width(), 3);
+        assert_eq!(framebuffer.height(), 2);
+        assert_eq!(framebuffer.pixels().len(), 6);
+        assert!(framebuffer.pixels().iter().all(|p| *p == Color::default()));
+        assert!(framebuffer.sample_counts().iter().all(|n| *n == 0));
+    }
+
+    #[test]
+    fn test_pixels_are_row_major() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        let color = Color::new(2.0, 0.5, 0.25);
+        framebuffer.set_pixel(1, 1, color, 8);
+        assert_eq!(framebuffer.pixel(1, 1), color);
+        assert_eq!(framebuffer.sample_count(1, 1), 8);
+        assert_eq!(framebuffer.pixels()[4], color);
+        assert_eq!(framebuffer.sample_counts()[4], 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_pixel_out_of_bounds() {
+        Framebuffer::new(3, 2).pixel(3, 0);
+    }
+}
@@ -1,10 +1,10 @@
-use core::f64;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Clone)]
@@ -16,6 +16,8 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // Set by `IdentifiedObject`, and 0 for objects without an ID.
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -36,6 +38,7 @@ impl HitRecord {
             u,
             v,
             front_face,
+            object_id: 0,
         }
     }
 
@@ -46,112 +49,53 @@ impl HitRecord {
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord>;
+    // Objects whose hits are random, like participating media, draw their
+    // random numbers from `sampler`; surfaces ignore it.
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord>;
     fn bounding_box(&self) -> &AABB;
-}
-
-pub struct Translate {
-    object: Arc<dyn Hittable>,
-    offset: Vec3,
-    bbox: AABB,
-}
-
-impl Translate {
-    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
-        let bbox = object.bounding_box().clone() + offset;
-        Translate {
-            object,
-            offset,
-            bbox,
-        }
-    }
-}
 
-impl Hittable for Translate {
-    fn bounding_box(&self) -> &AABB {
-        &self.bbox
+    // Density, with respect to solid angle, of `random` choosing `direction`
+    // from `origin`. Only objects that can be sampled as lights override it,
+    // and as lights are surfaces they test their hits with a fixed sampler.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
     }
 
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
-        let offset_ray = Ray::new(*ray.origin() - self.offset, *ray.direction(), ray.time());
-        match self.object.hit(&offset_ray, interval) {
-            Some(mut record) => {
-                record.point = record.point + self.offset;
-                Some(record)
-            }
-            None => None,
-        }
+    // A direction from `origin` towards a random point on the object.
+    fn random(&self, _origin: &Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
     }
 }
 
-pub struct RotateY {
+// Stamps the hits of `object` with `id`, which tells the objects of a scene
+// apart in the object ID AOV.
+pub struct IdentifiedObject {
     object: Arc<dyn Hittable>,
-    sin_theta: f64,
-    cos_theta: f64,
-    bbox: AABB,
+    id: u32,
 }
 
-impl RotateY {
-    pub fn new(object: Arc<dyn Hittable>, theta: f64) -> Self {
-        let radians = theta.to_radians();
-        let sin_theta = f64::sin(radians);
-        let cos_theta = f64::cos(radians);
-
-        let bbox = object.bounding_box().clone();
-        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
-        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
-        (min, max) = itertools::iproduct!(0..2, 0..2, 0..2)
-            .map(|(i, j, k)| {
-                let x = i as f64 * bbox.x.max + (1 - i) as f64 * bbox.x.min;
-                let y = j as f64 * bbox.y.max + (1 - j) as f64 * bbox.y.min;
-                let z = k as f64 * bbox.z.max + (1 - k) as f64 * bbox.z.min;
-                let new_x = cos_theta * x + sin_theta * z;
-                let new_z = -sin_theta * x + cos_theta * z;
-                Point3::new(new_x, y, new_z)
-            })
-            .fold((min, max), |(min, max), p| (min.min(&p), max.max(&p)));
-        let bbox = AABB::from_extremes(&min, &max);
-
-        RotateY {
-            object,
-            sin_theta,
-            cos_theta,
-            bbox,
-        }
+impl IdentifiedObject {
+    pub fn new(object: Arc<dyn Hittable>, id: u32) -> Self {
+        IdentifiedObject { object, id }
     }
 }
 
-impl Hittable for RotateY {
+impl Hittable for IdentifiedObject {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut record = self.object.hit(ray, interval, sampler)?;
+        record.object_id = self.id;
+        Some(record)
+    }
+
     fn bounding_box(&self) -> &AABB {
-        &self.bbox
+        self.object.bounding_box()
     }
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
-        let origin = Point3::new(
-            (self.cos_theta * ray.origin().x) - (self.sin_theta * ray.origin().z),
-            ray.origin().y,
-            (self.sin_theta * ray.origin().x) + (self.cos_theta * ray.origin().z),
-        );
-        let direction = Point3::new(
-            (self.cos_theta * ray.direction().x) - (self.sin_theta * ray.direction().z),
-            ray.direction().y,
-            (self.sin_theta * ray.direction().x) + (self.cos_theta * ray.direction().z),
-        );
-        let rotated_ray = Ray::new(origin, direction, ray.time());
-        match self.object.hit(&rotated_ray, interval) {
-            Some(mut record) => {
-                record.point = Point3::new(
-                    (self.cos_theta * record.point.x) + (self.sin_theta * record.point.z),
-                    record.point.y,
-                    (-self.sin_theta * record.point.x) + (self.cos_theta * record.point.z),
-                );
-                record.normal = Vec3::new(
-                    (self.cos_theta * record.normal.x) + (self.sin_theta * record.normal.z),
-                    record.normal.y,
-                    (-self.sin_theta * record.normal.x) + (self.cos_theta * record.normal.z),
-                );
-                Some(record)
-            }
-            None => None,
-        }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
     }
 }
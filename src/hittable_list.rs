@@ -4,12 +4,20 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
 
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
     bbox: AABB,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
@@ -36,35 +44,46 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut result: Option<HitRecord> = None;
         let mut closest_so_far = interval.max;
 
         for object in &self.objects {
-            if let Some(record) = object.hit(ray, &Interval::new(interval.min, closest_so_far)) {
+            if let Some(record) =
+                object.hit(ray, &Interval::new(interval.min, closest_so_far), sampler)
+            {
                 closest_so_far = record.t;
                 result = Some(record);
             }
         }
-        return result;
+        result
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let index = sampler.random_index(self.objects.len());
+        self.objects[index].random(origin, sampler)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{
-        material::Material,
-        ray::Ray,
-        sphere::Sphere,
-        vec3::{Point3, Vec3},
-    };
+    use crate::{material::Material, ray::Ray, sampler::IndependentSampler, sphere::Sphere};
 
     struct TestMaterial;
     impl Material for TestMaterial {}
 
     #[test]
     fn test_ray_hits_one_sphere_in_list() {
+        let mut sampler = IndependentSampler::new(0);
         let mut world = HittableList::new();
         let sphere = Arc::new(Sphere::stationary(
             Vec3::new(0.0, 0.0, -1.0),
@@ -73,12 +92,8 @@ mod tests {
         ));
         world.add(sphere);
         let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 2.0);
-        let result = world.hit(&ray, &Interval::new(0.001, f64::INFINITY));
-        assert_eq!(
-            result.is_some(),
-            true,
-            "Ray should hit the sphere in the world"
-        );
+        let result = world.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler);
+        assert!(result.is_some(), "Ray should hit the sphere in the world");
         let record = result.unwrap();
         assert!(
             (record.t - 0.5).abs() < 1e-6,
@@ -94,6 +109,7 @@ mod tests {
 
     #[test]
     fn test_ray_misses_all_objects() {
+        let mut sampler = IndependentSampler::new(0);
         let mut world = HittableList::new();
         let sphere = Arc::new(Sphere::stationary(
             Vec3::new(0.0, 0.0, -5.0),
@@ -102,11 +118,7 @@ mod tests {
         ));
         world.add(sphere);
         let ray = Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 2.0);
-        let result = world.hit(&ray, &Interval::new(0.001, f64::INFINITY));
-        assert_eq!(
-            result.is_none(),
-            true,
-            "Ray should miss all objects in the world"
-        );
+        let result = world.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler);
+        assert!(result.is_none(), "Ray should miss all objects in the world");
     }
 }
@@ -51,7 +51,7 @@ impl Interval {
         if x > self.max {
             return self.max;
         }
-        return x;
+        x
     }
 
     pub fn expand(&self, delta: f64) -> Self {
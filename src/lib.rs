@@ -1,16 +1,36 @@
 pub mod aabb;
+pub mod aov;
 pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
 pub mod color;
 pub mod constant_medium;
+pub mod denoise;
+pub mod filter;
+pub mod flat_bvh;
+pub mod framebuffer;
 pub mod hittable;
 pub mod hittable_list;
 pub mod interval;
 pub mod material;
+pub mod matrix;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
 pub mod perlin;
 pub mod quad;
 pub mod ray;
+pub mod sampler;
 pub mod scene;
+pub mod scene_file;
+pub mod spectrum;
 pub mod sphere;
 pub mod texture;
+pub mod tile;
+pub mod tone_map;
+pub mod transform;
+pub mod triangle;
+pub mod triangle_mesh;
 pub mod vec3;
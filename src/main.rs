@@ -1,34 +1,224 @@
-use std::{env, fs::File, io::BufReader, path::Path};
+use std::{env, fmt::Display, fs, fs::File, io::BufReader, path::Path, path::PathBuf, process};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use dotenv::dotenv;
 
-use ray_tracer::camera::{Camera, CameraSettings};
+use ray_tracer::aov::Aov;
+use ray_tracer::bvh::SplitStrategy;
+use ray_tracer::camera::{Camera, CameraSettings, DEFAULT_SAMPLES_PER_PASS};
+use ray_tracer::denoise::Denoiser;
+use ray_tracer::output::{
+    OutputError, OutputFormat, write_aov, write_exr_layers, write_image, write_sample_counts,
+};
 use ray_tracer::scene::Scene;
+use ray_tracer::scene_file::SceneFile;
+use ray_tracer::tile::TileOrder;
+use ray_tracer::tone_map::{ToneMapOperator, ToneMapping};
 
 #[derive(Parser)]
 struct Args {
-    #[arg(short, long, value_enum)]
-    scene: Scene,
+    #[arg(short, long, value_enum, required_unless_present = "scene_file")]
+    scene: Option<Scene>,
 
-    #[arg(short, long, default_value = "image.ppm")]
-    output: String,
+    #[arg(long, conflicts_with = "scene")]
+    scene_file: Option<PathBuf>,
+
+    // Checked before rendering, so that a typo does not waste a long render.
+    #[arg(short, long, default_value = "image.ppm", value_parser = image_path)]
+    output: PathBuf,
+
+    #[arg(long, value_enum, default_value = "median")]
+    bvh: BvhSplit,
+
+    #[arg(long, default_value_t = 4)]
+    bvh_leaf_size: usize,
+
+    // Prints the shape of the BVHs built for the scene before rendering.
+    #[arg(long)]
+    bvh_stats: bool,
+
+    // Seeds every random draw, from the scene layout to the pixel samples.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    // Samples taken for every pixel before the checkpoint is written.
+    #[arg(long, default_value_t = DEFAULT_SAMPLES_PER_PASS)]
+    samples_per_pass: i32,
+
+    // Width and height in pixels of the tiles that the threads render.
+    #[arg(long, default_value_t = 16)]
+    tile_size: u32,
+
+    #[arg(long, value_enum, default_value = "scanline")]
+    tile_order: TileOrder,
+
+    // Saves the accumulated samples to this file after every pass.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    // Continues from the checkpoint file until the camera's sample count is
+    // reached, which may be higher than when it was written.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    // Exposure compensation in stops, applied before tone mapping.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f64,
+
+    #[arg(long, value_enum, default_value = "clamp")]
+    tone_map: ToneMapOperator,
+
+    // Dithers 8-bit images to avoid banding in smooth gradients.
+    #[arg(long)]
+    dither: bool,
+
+    // Filters the noise out of the image, guided by its albedo and normals,
+    // before tone mapping.
+    #[arg(long)]
+    denoise: bool,
+
+    // Also writes these AOVs next to the output, as `image.albedo.png` for
+    // `image.png`, or as layers of the output if it is an OpenEXR file.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    aovs: Vec<Aov>,
+
+    // Also writes the number of samples taken for each pixel as an image.
+    #[arg(long, value_parser = image_path)]
+    sample_counts: Option<PathBuf>,
+}
+
+// A path whose extension names a supported image format.
+fn image_path(value: &str) -> Result<PathBuf, OutputError> {
+    let path = PathBuf::from(value);
+    match OutputFormat::from_path(&path) {
+        Some(_) => Ok(path),
+        None => Err(OutputError::UnsupportedFormat { file: path }),
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum BvhSplit {
+    Median,
+    Sah,
 }
 
-fn build_camera(scene: Scene) -> Camera {
+impl Args {
+    fn split_strategy(&self) -> SplitStrategy {
+        match self.bvh {
+            BvhSplit::Median => SplitStrategy::Median,
+            BvhSplit::Sah => SplitStrategy::sah(self.bvh_leaf_size),
+        }
+    }
+}
+
+fn build_camera(scene: &Scene) -> Camera {
     let settings_dir = env::var("CAMERA_SETTINGS_DIRECTORY").unwrap();
     let settings_path = Path::new(&settings_dir).join(format!("{}.json", scene.to_str()));
-    let file = File::open(settings_path).unwrap();
-    let reader = BufReader::new(file);
-    let settings: CameraSettings = serde_json::from_reader(reader).unwrap();
+    let file = File::open(&settings_path).unwrap();
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+    let settings: CameraSettings = serde_path_to_error::deserialize(&mut deserializer)
+        .unwrap_or_else(|error| {
+            eprintln!(
+                "error: {}: {}: {}",
+                settings_path.display(),
+                error.path(),
+                error.inner()
+            );
+            process::exit(1);
+        });
     Camera::from_settings(settings)
 }
 
+fn load_scene_file(path: &Path, strategy: SplitStrategy, seed: u64) -> SceneFile {
+    SceneFile::load(path, strategy, seed).unwrap_or_else(|error| {
+        eprintln!("error: {}: {error}", path.display());
+        process::exit(1);
+    })
+}
+
+fn or_exit<T>(result: Result<T, impl Display>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("error: {error}");
+        process::exit(1);
+    })
+}
+
 fn main() {
     dotenv().ok();
     let args = Args::parse();
-    let scene = args.scene.build();
-    let camera = build_camera(args.scene);
-    let mut output = File::create(args.output).unwrap();
-    camera.render(&scene, &mut output);
+    let strategy = args.split_strategy();
+    // `scene_source` identifies the objects in checkpoints.
+    let (scene, camera, scene_source) = match (&args.scene, &args.scene_file) {
+        (_, Some(path)) => {
+            let scene_file = load_scene_file(path, strategy, args.seed);
+            let source = or_exit(fs::read(path));
+            let camera = Camera::from_settings(scene_file.camera);
+            (scene_file.objects, camera, source)
+        }
+        (Some(scene), None) => {
+            let source = scene.to_str().as_bytes().to_vec();
+            (
+                scene.build(strategy, args.seed),
+                build_camera(scene),
+                source,
+            )
+        }
+        (None, None) => unreachable!("clap requires either --scene or --scene-file"),
+    };
+    let camera = camera
+        .with_seed(args.seed)
+        .with_samples_per_pass(args.samples_per_pass)
+        .with_tiles(args.tile_size, args.tile_order);
+    if args.bvh_stats {
+        for (name, stats) in &scene.bvh_stats {
+            eprintln!("bvh {name}: {stats}");
+        }
+    }
+    let mut checkpoint = match &args.checkpoint {
+        Some(file) if args.resume => or_exit(camera.resume_checkpoint(file, &scene_source)),
+        _ => camera.new_checkpoint(&scene_source),
+    };
+    or_exit(camera.render_passes(
+        &scene.world,
+        &scene.lights,
+        &mut checkpoint,
+        args.checkpoint.as_deref(),
+    ));
+    let mut framebuffer = checkpoint.framebuffer();
+    if args.denoise {
+        framebuffer = Denoiser::default().denoise(
+            &framebuffer,
+            &checkpoint.aov(Aov::Albedo),
+            &checkpoint.aov(Aov::Normal),
+            &checkpoint.variances(),
+        );
+    }
+    let tone_mapping = ToneMapping {
+        exposure: args.exposure,
+        operator: args.tone_map,
+        dither: args.dither,
+    };
+    let aovs = args
+        .aovs
+        .iter()
+        .map(|aov| (*aov, checkpoint.aov(*aov)))
+        .collect::<Vec<_>>();
+    if OutputFormat::from_path(&args.output) == Some(OutputFormat::Exr) && !aovs.is_empty() {
+        let mut layers = vec![("beauty", &framebuffer)];
+        layers.extend(aovs.iter().map(|(aov, image)| (aov.name(), image)));
+        or_exit(write_exr_layers(&args.output, &layers));
+    } else {
+        or_exit(write_image(&args.output, &framebuffer, &tone_mapping));
+        for (aov, image) in &aovs {
+            or_exit(write_aov(
+                &aov.path(&args.output),
+                image,
+                *aov,
+                &tone_mapping,
+            ));
+        }
+    }
+    if let Some(file) = &args.sample_counts {
+        or_exit(write_sample_counts(file, &framebuffer));
+    }
 }
@@ -1,26 +1,117 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use rand::random;
-
 use crate::{
     color::Color,
     hittable::HitRecord,
+    microfacet,
+    onb::OrthonormalBasis,
+    pdf::{CosinePdf, FuzzyReflectionPdf, LayeredPdf, MicrofacetPdf, Pdf, SpherePdf},
     ray::Ray,
+    sampler::Sampler,
+    spectrum::{Dispersion, REFERENCE_WAVELENGTH},
     texture::{SolidColor, Texture},
-    vec3::{Point3, Vec3},
+    vec3::Vec3,
 };
 
+pub enum ScatterRecord {
+    // The next direction is importance sampled from `pdf`, combined with
+    // sampling the lights, and weighted by the material's `scattering_pdf`.
+    Sampled {
+        attenuation: Color,
+        pdf: Box<dyn Pdf>,
+    },
+    // The next ray is chosen by the material alone, like a mirror reflection
+    // or a refraction, and is followed as is without sampling the lights.
+    Specular {
+        attenuation: Color,
+        ray: Ray,
+    },
+}
+
 pub trait Material: Send + Sync {
-    fn scatter(&self, _ray_in: &Ray, _record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         None
     }
-    fn emitted(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
+    fn scattering_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+    // The light scattered towards the origin of `ray_in` per unit of light
+    // arriving along `scattered`, cosine included, for the `attenuation`
+    // returned by `scatter`. Materials whose colour does not depend on the
+    // directions scale it by `scattering_pdf`.
+    fn scattering(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+    ) -> Color {
+        self.scattering_pdf(ray_in, record, scattered) * *attenuation
+    }
+    fn emitted(&self, _record: &HitRecord) -> Color {
         Color {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
         }
     }
+    // Tells the materials of a scene apart in the material ID AOV; 0 for
+    // materials without an ID.
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+// Gives `material` an ID, and otherwise behaves like it.
+pub struct IdentifiedMaterial {
+    material: Arc<dyn Material>,
+    id: u32,
+}
+
+impl IdentifiedMaterial {
+    pub fn new(material: Arc<dyn Material>, id: u32) -> Self {
+        IdentifiedMaterial { material, id }
+    }
+}
+
+impl Material for IdentifiedMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter(ray_in, record, sampler)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(ray_in, record, scattered)
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+    ) -> Color {
+        self.material
+            .scattering(ray_in, record, scattered, attenuation)
+    }
+
+    fn emitted(&self, record: &HitRecord) -> Color {
+        self.material.emitted(record)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
 }
 
 pub struct Lambertian {
@@ -38,14 +129,20 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = record.normal + Vec3::random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = record.normal
-        }
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let attenuation = self.texture.value(record.u, record.v, &record.point);
-        let ray_out = Ray::new(record.point, scatter_direction, ray_in.time());
-        return Some((attenuation, ray_out));
+        let pdf = Box::new(CosinePdf::new(&record.normal));
+        Some(ScatterRecord::Sampled { attenuation, pdf })
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = record.normal.dot(&scattered.direction().unit_vector());
+        f64::max(0.0, cos_theta / PI)
     }
 }
 
@@ -64,26 +161,403 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
-        let mut reflected = Vec3::reflect(ray_in.direction(), &record.normal);
-        reflected = reflected.unit_vector() + self.fuzz * Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let reflected = Vec3::reflect(ray_in.direction(), &record.normal);
         let attenuation = self.albedo;
-        let scattered = Ray::new(record.point, reflected, ray_in.time());
-        if scattered.direction().dot(&record.normal) > 0.0 {
-            Some((attenuation, scattered))
-        } else {
-            None
+        if self.fuzz <= 0.0 {
+            let scattered = Ray::new(record.point, reflected, ray_in.time());
+            return Some(ScatterRecord::Specular {
+                attenuation,
+                ray: scattered,
+            });
+        }
+        let pdf = Box::new(FuzzyReflectionPdf::new(&reflected, self.fuzz));
+        Some(ScatterRecord::Sampled { attenuation, pdf })
+    }
+
+    // Directions are sampled exactly from this density, so that the albedo is
+    // the weight of every sample; those below the surface are absorbed.
+    fn scattering_pdf(&self, ray_in: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        if scattered.direction().dot(&record.normal) <= 0.0 {
+            return 0.0;
+        }
+        let reflected = Vec3::reflect(ray_in.direction(), &record.normal);
+        FuzzyReflectionPdf::new(&reflected, self.fuzz).value(scattered.direction())
+    }
+}
+
+// A Cook-Torrance microfacet reflection over a diffuse base, with the GGX
+// distribution of normals, Smith's masking and shadowing and Schlick's Fresnel
+// approximation. Dielectrics reflect 4% at normal incidence and diffuse the
+// rest in their base colour; metals tint their reflection with it and do not
+// diffuse. The roughness and metallic textures are read from their red
+// channel.
+pub struct Microfacet {
+    base_color: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+}
+
+impl Microfacet {
+    pub fn new(
+        base_color: Arc<dyn Texture>,
+        roughness: Arc<dyn Texture>,
+        metallic: Arc<dyn Texture>,
+    ) -> Self {
+        Microfacet {
+            base_color,
+            roughness,
+            metallic,
+        }
+    }
+
+    pub fn from_values(base_color: Color, roughness: f64, metallic: f64) -> Self {
+        let scalar = |value| Arc::new(SolidColor::new(Color::new(value, value, value)));
+        Microfacet::new(
+            Arc::new(SolidColor::new(base_color)),
+            scalar(roughness),
+            scalar(metallic),
+        )
+    }
+
+    // The width of the distribution of normals and the metallic weight at the
+    // hit.
+    fn parameters(&self, record: &HitRecord) -> (f64, f64) {
+        let value = |texture: &Arc<dyn Texture>| {
+            texture
+                .value(record.u, record.v, &record.point)
+                .r
+                .clamp(0.0, 1.0)
+        };
+        (
+            microfacet::alpha(value(&self.roughness)),
+            value(&self.metallic),
+        )
+    }
+}
+
+impl Material for Microfacet {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.base_color.value(record.u, record.v, &record.point);
+        let (alpha, metallic) = self.parameters(record);
+        // Half of the samples go to the specular lobe of dielectrics, all of
+        // them for metals.
+        let pdf = Box::new(MicrofacetPdf::new(
+            &record.normal,
+            &-*ray_in.direction(),
+            alpha,
+            0.5 + 0.5 * metallic,
+        ));
+        Some(ScatterRecord::Sampled { attenuation, pdf })
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+    ) -> Color {
+        let view = -ray_in.direction().unit_vector();
+        let light = scattered.direction().unit_vector();
+        let (cos_view, cos_light) = (record.normal.dot(&view), record.normal.dot(&light));
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return Color::default();
         }
+        let (alpha, metallic) = self.parameters(record);
+        let half = (view + light).unit_vector();
+        let f0 = (1.0 - metallic) * Color::new(0.04, 0.04, 0.04) + metallic * *attenuation;
+        let fresnel = microfacet::schlick(f0, view.dot(&half));
+        let specular = microfacet::distribution(alpha, record.normal.dot(&half))
+            * microfacet::shadowing(alpha, cos_view, cos_light)
+            / (4.0 * cos_view * cos_light)
+            * fresnel;
+        // What enters the surface on the way in and leaves it on the way out,
+        // which keeps the diffuse lobe from adding to grazing highlights.
+        let white = Color::new(1.0, 1.0, 1.0);
+        let transmission = (white - microfacet::schlick(f0, cos_view))
+            * (white - microfacet::schlick(f0, cos_light));
+        let diffuse = (1.0 - metallic) / PI * transmission * *attenuation;
+        cos_light * (specular + diffuse)
     }
 }
 
+// A Disney-style "principled" material layering its lobes like `Microfacet`: a
+// GGX specular reflection over a diffuse base with a sheen that brightens it at
+// grazing angles, a glass interface as rough as the surface chosen with
+// probability `specular_transmission` instead of the diffuse base, and a clear
+// GGX coat over everything with an index of refraction of 1.5. Dielectric
+// reflectances come from `refraction_index`, scaled by `specular`. The scalar
+// textures, the index included, are read from their red channel.
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    specular_transmission: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_roughness: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    sheen_tint: Arc<dyn Texture>,
+    refraction_index: Arc<dyn Texture>,
+}
+
+// The scalar parameters of a `Principled` material at a hit.
+struct PrincipledParameters {
+    metallic: f64,
+    roughness: f64,
+    alpha: f64,
+    specular: f64,
+    specular_transmission: f64,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    refraction_index: f64,
+}
+
+impl Principled {
+    // A rough dielectric in `base_color`, without transmission, coat or sheen.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Principled {
+            base_color,
+            metallic: uniform(0.0),
+            roughness: uniform(0.5),
+            specular: uniform(1.0),
+            specular_transmission: uniform(0.0),
+            clearcoat: uniform(0.0),
+            clearcoat_roughness: uniform(0.1),
+            sheen: uniform(0.0),
+            sheen_tint: uniform(0.5),
+            refraction_index: uniform(1.5),
+        }
+    }
+
+    pub fn from_albedo(base_color: Color) -> Self {
+        Principled::new(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn with_metallic(self, metallic: Arc<dyn Texture>) -> Self {
+        Principled { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: Arc<dyn Texture>) -> Self {
+        Principled { roughness, ..self }
+    }
+
+    pub fn with_specular(self, specular: Arc<dyn Texture>) -> Self {
+        Principled { specular, ..self }
+    }
+
+    pub fn with_specular_transmission(self, specular_transmission: Arc<dyn Texture>) -> Self {
+        Principled {
+            specular_transmission,
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: Arc<dyn Texture>) -> Self {
+        Principled { clearcoat, ..self }
+    }
+
+    pub fn with_clearcoat_roughness(self, clearcoat_roughness: Arc<dyn Texture>) -> Self {
+        Principled {
+            clearcoat_roughness,
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: Arc<dyn Texture>) -> Self {
+        Principled { sheen, ..self }
+    }
+
+    pub fn with_sheen_tint(self, sheen_tint: Arc<dyn Texture>) -> Self {
+        Principled { sheen_tint, ..self }
+    }
+
+    pub fn with_refraction_index(self, refraction_index: Arc<dyn Texture>) -> Self {
+        Principled {
+            refraction_index,
+            ..self
+        }
+    }
+
+    fn parameters(&self, record: &HitRecord) -> PrincipledParameters {
+        let red = |texture: &Arc<dyn Texture>| texture.value(record.u, record.v, &record.point).r;
+        let value = |texture: &Arc<dyn Texture>| red(texture).clamp(0.0, 1.0);
+        let roughness = value(&self.roughness);
+        PrincipledParameters {
+            metallic: value(&self.metallic),
+            roughness,
+            alpha: microfacet::alpha(roughness),
+            specular: value(&self.specular),
+            specular_transmission: value(&self.specular_transmission),
+            clearcoat: value(&self.clearcoat),
+            clearcoat_alpha: microfacet::alpha(value(&self.clearcoat_roughness)),
+            sheen: value(&self.sheen),
+            sheen_tint: value(&self.sheen_tint),
+            refraction_index: red(&self.refraction_index),
+        }
+    }
+
+    // The reflectance at normal incidence of a dielectric interface with
+    // `refraction_index`.
+    fn dielectric_f0(refraction_index: f64) -> f64 {
+        let r0 = (refraction_index - 1.0) / (refraction_index + 1.0);
+        r0 * r0
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let attenuation = self.base_color.value(record.u, record.v, &record.point);
+        let parameters = self.parameters(record);
+        // The glass lobe replaces the others with its probability, so that
+        // neither needs weighting by it.
+        let transmission = parameters.specular_transmission * (1.0 - parameters.metallic);
+        // Like tinted glass, it colours only the light that refracts in, and
+        // not again on the way out or at internal reflections.
+        if sampler.random() < transmission {
+            return Dielectric::new(parameters.refraction_index)
+                .with_roughness(parameters.roughness)
+                .with_tint(attenuation)
+                .scatter(ray_in, record, sampler);
+        }
+        let view = -*ray_in.direction();
+        let coat = MicrofacetPdf::new(&record.normal, &view, parameters.clearcoat_alpha, 1.0);
+        let base = MicrofacetPdf::new(
+            &record.normal,
+            &view,
+            parameters.alpha,
+            0.5 + 0.5 * parameters.metallic,
+        );
+        let pdf = Box::new(LayeredPdf::new(coat, base, 0.25 * parameters.clearcoat));
+        Some(ScatterRecord::Sampled { attenuation, pdf })
+    }
+
+    fn scattering(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        scattered: &Ray,
+        attenuation: &Color,
+    ) -> Color {
+        let view = -ray_in.direction().unit_vector();
+        let light = scattered.direction().unit_vector();
+        let (cos_view, cos_light) = (record.normal.dot(&view), record.normal.dot(&light));
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return Color::default();
+        }
+        let parameters = self.parameters(record);
+        let metallic = parameters.metallic;
+        let half = (view + light).unit_vector();
+        let (cos_half, cos_difference) = (record.normal.dot(&half), view.dot(&half));
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let dielectric_f0 =
+            (parameters.specular * Principled::dielectric_f0(parameters.refraction_index)).min(1.0);
+        let f0 = (1.0 - metallic) * dielectric_f0 * white + metallic * *attenuation;
+        let specular = microfacet::distribution(parameters.alpha, cos_half)
+            * microfacet::shadowing(parameters.alpha, cos_view, cos_light)
+            / (4.0 * cos_view * cos_light)
+            * microfacet::schlick(f0, cos_difference);
+        let transmission = (white - microfacet::schlick(f0, cos_view))
+            * (white - microfacet::schlick(f0, cos_light));
+        let diffuse = (1.0 - metallic) / PI * transmission * *attenuation;
+        // The tint is the hue of the base colour, without its brightness.
+        let brightness = attenuation.luminance();
+        let tint = match brightness > 0.0 {
+            true => *attenuation / brightness,
+            false => white,
+        };
+        let sheen_color = (1.0 - parameters.sheen_tint) * white + parameters.sheen_tint * tint;
+        let sheen = (1.0 - metallic) * parameters.sheen / PI
+            * (1.0 - cos_difference.clamp(0.0, 1.0)).powi(5)
+            * sheen_color;
+        let base = specular + diffuse + sheen;
+
+        let coat_f0 = Color::new(0.04, 0.04, 0.04);
+        let coat_alpha = parameters.clearcoat_alpha;
+        let coat = parameters.clearcoat
+            * microfacet::distribution(coat_alpha, cos_half)
+            * microfacet::shadowing(coat_alpha, cos_view, cos_light)
+            / (4.0 * cos_view * cos_light)
+            * microfacet::schlick(coat_f0, cos_difference);
+        // What the coat lets through on the way in and out reaches the base.
+        let coat_transmission = (1.0
+            - parameters.clearcoat * microfacet::schlick(coat_f0, cos_view).r)
+            * (1.0 - parameters.clearcoat * microfacet::schlick(coat_f0, cos_light).r);
+        cos_light * (coat + coat_transmission * base)
+    }
+}
+
+// A texture of the same `value` everywhere, for scalar parameters.
+fn uniform(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+// Glass, reflecting or refracting with the Fresnel reflectance as probability.
+// Light travelling inside it is absorbed following the Beer-Lambert law, by
+// `absorption` per unit of length for each channel, which the rays refracting
+// in carry so that every segment inside is absorbed, whatever it ends on. What
+// refracts into it is filtered by `tint`. A `roughness` above 0 scatters it off GGX microfacets
+// instead of a smooth interface, which frosts it. With a `dispersion` curve,
+// the refractive index of spectral paths depends on their hero wavelength.
 pub struct Dielectric {
     pub refraction_index: f64,
+    dispersion: Option<Dispersion>,
+    tint: Color,
+    absorption: Color,
+    roughness: f64,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Dielectric { refraction_index }
+        Dielectric {
+            refraction_index,
+            dispersion: None,
+            tint: Color::new(1.0, 1.0, 1.0),
+            absorption: Color::default(),
+            roughness: 0.0,
+        }
+    }
+
+    // Renders without wavelengths use the index of the d line.
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Dielectric {
+            dispersion: Some(dispersion),
+            ..Dielectric::new(dispersion.refraction_index(REFERENCE_WAVELENGTH))
+        }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        Dielectric { tint, ..self }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Dielectric { absorption, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        Dielectric {
+            roughness: roughness.clamp(0.0, 1.0),
+            ..self
+        }
     }
 
     fn reflectance(&self, cosine: f64, refraction_index: f64) -> f64 {
@@ -91,29 +565,118 @@ impl Dielectric {
         r0 = r0 * r0;
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_index = if record.front_face {
-            1.0 / self.refraction_index
+    // Reflects or refracts the unit `direction` off an interface with `normal`
+    // into a medium of `refraction_index`, and tells whether it reflected.
+    fn scatter_off(
+        &self,
+        direction: &Vec3,
+        normal: &Vec3,
+        front_face: bool,
+        refraction_index: f64,
+        sampler: &mut dyn Sampler,
+    ) -> (Vec3, bool) {
+        let refraction_index = if front_face {
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
-        let unit_direction = ray_in.direction().unit_vector();
-        let cos_theta = -unit_direction.dot(&record.normal).min(1.0);
+        let cos_theta = -direction.dot(normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_index * sin_theta > 1.0;
-        let reflectance_threshold: f64 = random();
+        let reflectance_threshold = sampler.random();
         let does_reflect = self.reflectance(cos_theta, refraction_index) > reflectance_threshold;
-        let direction = if cannot_refract || does_reflect {
-            Vec3::reflect(&unit_direction, &record.normal)
+        if cannot_refract || does_reflect {
+            (Vec3::reflect(direction, normal), true)
         } else {
-            Vec3::refract(&unit_direction, &record.normal, refraction_index)
+            (Vec3::refract(direction, normal, refraction_index), false)
+        }
+    }
+
+    // Scatters `ray_in` off a microfacet normal sampled from those visible
+    // from it, weighted by the fraction of the scattered light that no other
+    // microfacet blocks. Directions that end up on the wrong side of the
+    // surface are absorbed.
+    fn rough_direction(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        refraction_index: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vec3, f64)> {
+        let alpha = microfacet::alpha(self.roughness);
+        let uvw = OrthonormalBasis::new(&record.normal);
+        let unit_direction = ray_in.direction().unit_vector();
+        let local = uvw.to_local(&-unit_direction);
+        let view = Vec3::new(local.x, local.y, local.z.max(1e-4)).unit_vector();
+        let normal = uvw.transform(&microfacet::sample_visible_normal(
+            &view,
+            alpha,
+            sampler.random_2d(),
+        ));
+        let (direction, reflected) = self.scatter_off(
+            &unit_direction,
+            &normal,
+            record.front_face,
+            refraction_index,
+            sampler,
+        );
+        let cos_out = direction.unit_vector().dot(&record.normal);
+        if (cos_out > 0.0) != reflected {
+            return None;
+        }
+        Some((direction, microfacet::masking(alpha, cos_out.abs())))
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        // Only the hero wavelength can follow a direction chosen with its own
+        // index.
+        let (refraction_index, wavelengths) = match (&self.dispersion, ray_in.wavelengths()) {
+            (Some(dispersion), Some(wavelengths)) => (
+                dispersion.refraction_index(wavelengths.hero()),
+                Some(wavelengths.terminate_secondary()),
+            ),
+            _ => (self.refraction_index, ray_in.wavelengths().copied()),
+        };
+        let (direction, weight) = if self.roughness > 0.0 {
+            self.rough_direction(ray_in, record, refraction_index, sampler)?
+        } else {
+            let unit_direction = ray_in.direction().unit_vector();
+            let (direction, _) = self.scatter_off(
+                &unit_direction,
+                &record.normal,
+                record.front_face,
+                refraction_index,
+                sampler,
+            );
+            (direction, 1.0)
         };
-        let scattered = Ray::new(record.point, direction, ray_in.time());
-        Some((attenuation, scattered))
+        let mut attenuation = Color::new(weight, weight, weight);
+        // Reflected rays stay in the medium they came through; refracted ones
+        // enter the glass or leave it for the air.
+        let mut absorption = None;
+        if direction.dot(&record.normal) < 0.0 {
+            if record.front_face {
+                attenuation = attenuation * self.tint;
+                absorption = Some(self.absorption);
+            } else {
+                absorption = Some(Color::default());
+            }
+        }
+        let scattered = Ray::new(record.point, direction, ray_in.time())
+            .with_wavelengths(wavelengths)
+            .with_absorption(absorption);
+        Some(ScatterRecord::Specular {
+            attenuation,
+            ray: scattered,
+        })
     }
 }
 
@@ -133,8 +696,13 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, u: f64, v: f64, point: &Point3) -> Color {
-        self.texture.value(u, v, point)
+    // Lights only emit on the side their normal points to, so that a light
+    // placed against a wall does not flood the gap behind it.
+    fn emitted(&self, record: &HitRecord) -> Color {
+        if !record.front_face {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.texture.value(record.u, record.v, &record.point)
     }
 }
 
@@ -153,9 +721,276 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, record: &HitRecord) -> Option<(Color, Ray)> {
-        let scattered = Ray::new(record.point, Vec3::random_unit_vector(), ray_in.time());
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let attenuation = self.texture.value(record.u, record.v, &record.point);
-        Some((attenuation, scattered))
+        let pdf = Box::new(SpherePdf);
+        Some(ScatterRecord::Sampled { attenuation, pdf })
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sampler::IndependentSampler, vec3::Point3};
+
+    // The fraction of the light from a white sky that the material reflects
+    // towards a viewer at `cos_view` from the normal, estimated with its own
+    // importance sampling.
+    fn albedo(material: impl Material + 'static, cos_view: f64) -> f64 {
+        let material = Arc::new(material);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let record = HitRecord::new(
+            Point3::default(),
+            normal,
+            material.clone(),
+            1.0,
+            0.0,
+            0.0,
+            true,
+        );
+        let sin_view = (1.0 - cos_view * cos_view).sqrt();
+        let ray_in = Ray::new(
+            Point3::new(-sin_view, 0.0, cos_view),
+            Vec3::new(sin_view, 0.0, -cos_view),
+            0.0,
+        );
+        let mut sampler = IndependentSampler::new(0);
+        let samples = 100_000;
+        (0..samples)
+            .map(|_| {
+                let (attenuation, pdf) = match material.scatter(&ray_in, &record, &mut sampler) {
+                    Some(ScatterRecord::Sampled { attenuation, pdf }) => (attenuation, pdf),
+                    // Transmitted or reflected with a single weight.
+                    Some(ScatterRecord::Specular { attenuation, .. }) => return attenuation.r,
+                    None => return 0.0,
+                };
+                let direction = pdf.generate(&mut sampler);
+                let scattered = Ray::new(Point3::default(), direction, 0.0);
+                let pdf_value = pdf.value(&direction);
+                match pdf_value > 0.0 {
+                    true => {
+                        material
+                            .scattering(&ray_in, &record, &scattered, &attenuation)
+                            .r
+                            / pdf_value
+                    }
+                    false => 0.0,
+                }
+            })
+            .sum::<f64>()
+            / samples as f64
+    }
+
+    #[test]
+    fn test_microfacet_conserves_energy() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        for cos_view in [1.0, 0.5, 0.1] {
+            // Smooth metals reflect nearly everything; rough ones lose what
+            // bounces more than once between the microfacets.
+            let smooth = albedo(Microfacet::from_values(white, 0.1, 1.0), cos_view);
+            assert!((0.97..1.01).contains(&smooth), "{cos_view}: {smooth}");
+            let rough = albedo(Microfacet::from_values(white, 0.8, 1.0), cos_view);
+            assert!((0.5..0.95).contains(&rough), "{cos_view}: {rough}");
+            let plastic = albedo(Microfacet::from_values(white, 0.3, 0.0), cos_view);
+            assert!((0.6..1.01).contains(&plastic), "{cos_view}: {plastic}");
+        }
+        let black = albedo(Microfacet::from_values(Color::default(), 0.3, 0.0), 1.0);
+        assert!((0.03..0.05).contains(&black), "{black}");
+    }
+
+    #[test]
+    fn test_principled_conserves_energy() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let uniform_white = || Principled::from_albedo(white);
+        for cos_view in [1.0, 0.5, 0.1] {
+            let plastic = albedo(uniform_white().with_roughness(uniform(0.3)), cos_view);
+            assert!((0.6..1.01).contains(&plastic), "{cos_view}: {plastic}");
+            let coated = albedo(
+                uniform_white()
+                    .with_clearcoat(uniform(1.0))
+                    .with_sheen(uniform(1.0)),
+                cos_view,
+            );
+            assert!((0.6..1.01).contains(&coated), "{cos_view}: {coated}");
+            let glass = albedo(
+                uniform_white()
+                    .with_roughness(uniform(0.0))
+                    .with_specular_transmission(uniform(1.0)),
+                cos_view,
+            );
+            assert!((glass - 1.0).abs() < 1e-9, "{cos_view}: {glass}");
+            let frosted = albedo(
+                uniform_white()
+                    .with_roughness(uniform(0.3))
+                    .with_specular_transmission(uniform(1.0)),
+                cos_view,
+            );
+            assert!((0.85..1.01).contains(&frosted), "{cos_view}: {frosted}");
+        }
+        // The coat reflects about 4% at normal incidence over a black base.
+        let coat = albedo(
+            Principled::from_albedo(Color::default())
+                .with_specular(uniform(0.0))
+                .with_clearcoat(uniform(1.0)),
+            1.0,
+        );
+        assert!((0.03..0.05).contains(&coat), "{coat}");
+    }
+
+    #[test]
+    fn test_transmissive_principled_tints_once() {
+        // Walks a path through a slab between z = 0 and z = -1, refracting and
+        // reflecting off its faces until it leaves, and returns its weight.
+        let walk = |material: &Arc<Principled>, sampler: &mut IndependentSampler| {
+            let mut ray = Ray::new(Point3::new(-0.5, 0.0, 1.0), Vec3::new(0.5, 0.0, -1.0), 0.0);
+            let mut throughput = Color::new(1.0, 1.0, 1.0);
+            let mut face = 0.0;
+            loop {
+                let outward = Vec3::new(0.0, 0.0, if face == 0.0 { 1.0 } else { -1.0 });
+                let t = (face - ray.origin().z) / ray.direction().z;
+                let mut record =
+                    HitRecord::new(ray.at(t), outward, material.clone(), t, 0.0, 0.0, false);
+                record.set_face_normal(&ray, &outward);
+                match material.scatter(&ray, &record, sampler) {
+                    Some(ScatterRecord::Specular {
+                        attenuation,
+                        ray: scattered,
+                    }) => {
+                        throughput = throughput * attenuation;
+                        ray = scattered;
+                    }
+                    _ => panic!("Expected a specular scatter"),
+                }
+                if ray.direction().dot(&outward) > 0.0 {
+                    return throughput;
+                }
+                face = -1.0 - face;
+            }
+        };
+        // Smooth, so that no path is lost between the microfacets.
+        let smooth_glass = |base_color| {
+            Arc::new(
+                Principled::from_albedo(base_color)
+                    .with_roughness(uniform(0.0))
+                    .with_specular_transmission(uniform(1.0)),
+            )
+        };
+        let mut sampler = IndependentSampler::new(0);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let glass = smooth_glass(white);
+        for _ in 0..10_000 {
+            let throughput = walk(&glass, &mut sampler);
+            assert!(throughput.distance(&white) < 1e-12, "{throughput:?}");
+        }
+        // Paths reflected off the slab keep their colour, and those through it
+        // take the base colour once.
+        let base_color = Color::new(0.2, 0.5, 0.9);
+        let tinted = smooth_glass(base_color);
+        let transmitted = (0..10_000)
+            .filter(|_| {
+                let throughput = walk(&tinted, &mut sampler);
+                assert!(
+                    throughput.distance(&white) < 1e-12 || throughput.distance(&base_color) < 1e-12,
+                    "{throughput:?}"
+                );
+                throughput.distance(&base_color) < 1e-12
+            })
+            .count();
+        assert!(transmitted > 9_000, "{transmitted}");
+    }
+
+    #[test]
+    fn test_rough_transmissive_principled_spreads_refraction() {
+        // At normal incidence, smooth glass refracts straight through.
+        let transmitted = |roughness| {
+            let material = Arc::new(
+                Principled::from_albedo(Color::new(1.0, 1.0, 1.0))
+                    .with_roughness(uniform(roughness))
+                    .with_specular_transmission(uniform(1.0)),
+            );
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            let record = HitRecord::new(
+                Point3::default(),
+                normal,
+                material.clone(),
+                1.0,
+                0.0,
+                0.0,
+                true,
+            );
+            let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), -normal, 0.0);
+            let mut sampler = IndependentSampler::new(0);
+            (0..10_000)
+                .filter_map(|_| match material.scatter(&ray, &record, &mut sampler) {
+                    Some(ScatterRecord::Specular { ray, .. }) => {
+                        Some(ray.direction().unit_vector())
+                    }
+                    _ => None,
+                })
+                .filter(|direction| direction.z < 0.0)
+                .collect::<Vec<_>>()
+        };
+        let smooth = transmitted(0.0);
+        assert!(smooth.len() > 9_000, "{}", smooth.len());
+        assert!(smooth.iter().all(|direction| direction.z < -1.0 + 1e-12));
+        let rough = transmitted(0.5);
+        let spread = rough
+            .iter()
+            .filter(|direction| direction.z > -0.999)
+            .count();
+        assert!(spread > rough.len() / 2, "{spread} of {}", rough.len());
+    }
+
+    #[test]
+    fn test_dielectric_absorbs_inside_and_tints_what_enters() {
+        // An index of 1 refracts everything straight through.
+        let absorption = Color::new(0.5, 0.0, 1.0);
+        let material = Arc::new(
+            Dielectric::new(1.0)
+                .with_absorption(absorption)
+                .with_tint(Color::new(0.2, 0.4, 0.8)),
+        );
+        let ray = Ray::new(Point3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        let mut scatter = |front_face| {
+            let record = HitRecord::new(
+                Point3::default(),
+                Vec3::new(0.0, 0.0, 1.0),
+                material.clone(),
+                2.0,
+                0.0,
+                0.0,
+                front_face,
+            );
+            match material.scatter(&ray, &record, &mut sampler) {
+                Some(ScatterRecord::Specular { attenuation, ray }) => (attenuation, ray),
+                _ => panic!("Expected a specular scatter"),
+            }
+        };
+        // Rays entering carry the absorption of the glass, which the camera
+        // applies over each of their segments, and rays leaving clear it.
+        let (entering, inside) = scatter(true);
+        assert!(entering.distance(&Color::new(0.2, 0.4, 0.8)) < 1e-12);
+        assert_eq!(inside.absorption(), Some(&absorption));
+        let (leaving, outside) = scatter(false);
+        assert_eq!(leaving, Color::new(1.0, 1.0, 1.0));
+        assert_eq!(outside.absorption(), Some(&Color::default()));
+    }
+
+    #[test]
+    fn test_rough_dielectric_conserves_energy() {
+        for cos_view in [1.0, 0.5] {
+            let frosted = albedo(Dielectric::new(1.5).with_roughness(0.3), cos_view);
+            assert!((0.85..1.01).contains(&frosted), "{cos_view}: {frosted}");
+        }
     }
 }
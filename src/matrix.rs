@@ -0,0 +1,193 @@
+use std::ops::Mul;
+
+use crate::vec3::{Point3, Vec3};
+
+// A 4x4 matrix acting on homogeneous coordinates, stored row by row. Points
+// are affected by the translation in the last column, vectors are not.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(rows: [[f64; 4]; 4]) -> Self {
+        Matrix4 { rows }
+    }
+
+    pub fn identity() -> Self {
+        Matrix4::scaling(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factor: Vec3) -> Self {
+        Matrix4::new([
+            [factor.x, 0.0, 0.0, 0.0],
+            [0.0, factor.y, 0.0, 0.0],
+            [0.0, 0.0, factor.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counterclockwise rotation by `angle` degrees when looking down `axis`
+    // towards the origin (Rodrigues' formula).
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let Vec3 { x, y, z } = axis.unit_vector();
+        let radians = angle.to_radians();
+        let (sin, cos) = radians.sin_cos();
+        let t = 1.0 - cos;
+        Matrix4::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Matrix4::new(rows)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. Returns `None` for
+    // singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut left = self.rows;
+        let mut right = Matrix4::identity().rows;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| left[a][column].abs().total_cmp(&left[b][column].abs()))
+                .unwrap();
+            if left[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+
+            let scale = 1.0 / left[column][column];
+            for j in 0..4 {
+                left[column][j] *= scale;
+                right[column][j] *= scale;
+            }
+            for i in (0..4).filter(|&i| i != column) {
+                let factor = left[i][column];
+                for j in 0..4 {
+                    left[i][j] -= factor * left[column][j];
+                    right[i][j] -= factor * right[column][j];
+                }
+            }
+        }
+        Some(Matrix4::new(right))
+    }
+
+    // Determinant of the upper-left 3x3 block, the factor by which volumes
+    // are scaled.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, point: &Point3) -> Point3 {
+        self.transform_vector(point) + Vec3::new(self.rows[0][3], self.rows[1][3], self.rows[2][3])
+    }
+
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        let row = |i: usize| {
+            self.rows[i][0] * vector.x + self.rows[i][1] * vector.y + self.rows[i][2] * vector.z
+        };
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+        Matrix4::new(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "Expected {b:?}, got {a:?}");
+    }
+
+    #[test]
+    fn test_rotation_about_axes() {
+        let quarter_turn = |axis| Matrix4::rotation(axis, 90.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_close(&quarter_turn(z).transform_vector(&x), &y);
+        assert_close(&quarter_turn(x).transform_vector(&y), &z);
+        assert_close(&quarter_turn(y).transform_vector(&z), &x);
+    }
+
+    #[test]
+    fn test_composition_applies_right_first() {
+        let matrix = Matrix4::translation(Vec3::new(1.0, 2.0, 3.0))
+            * Matrix4::scaling(Vec3::new(2.0, 2.0, 2.0));
+        let point = matrix.transform_point(&Point3::new(1.0, 1.0, 1.0));
+        assert_close(&point, &Point3::new(3.0, 4.0, 5.0));
+        let vector = matrix.transform_vector(&Vec3::new(1.0, 1.0, 1.0));
+        assert_close(&vector, &Vec3::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn test_inverse() {
+        let matrix = Matrix4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix4::scaling(Vec3::new(2.0, 0.5, -1.0));
+        let product = matrix * matrix.inverse().unwrap();
+        for (i, row) in product.rows.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-9);
+            }
+        }
+        assert!((matrix.determinant3() + 1.0).abs() < 1e-9);
+        assert!(
+            Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0))
+                .inverse()
+                .is_none()
+        );
+    }
+}
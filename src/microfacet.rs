@@ -0,0 +1,139 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, vec3::Vec3};
+
+// The isotropic GGX (Trowbridge-Reitz) model of rough surfaces, made of tiny
+// mirrors whose normals spread around the macroscopic normal, the z axis of the
+// local frames used here, with a width `alpha`. Perceptually linear
+// roughnesses are squared into `alpha`, which is kept away from zero where the
+// distribution degenerates into a mirror.
+pub fn alpha(roughness: f64) -> f64 {
+    (roughness * roughness).clamp(1e-3, 1.0)
+}
+
+// The density of microfacet normals whose cosine with the normal is `cos_h`,
+// with respect to solid angle and projected onto the surface.
+pub fn distribution(alpha: f64, cos_h: f64) -> f64 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let denominator = cos_h * cos_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+// Smith's masking function: the fraction of the microfacets facing a direction
+// whose cosine with the normal is `cos` that are not hidden by others.
+pub fn masking(alpha: f64, cos: f64) -> f64 {
+    if cos <= 0.0 {
+        return 0.0;
+    }
+    1.0 / (1.0 + lambda(alpha, cos))
+}
+
+// The height-correlated Smith masking-shadowing function: the fraction of the
+// microfacets that are seen both from the view and from the light. Facets
+// hidden from one are likely to be hidden from the other, which makes it
+// larger than the product of the two maskings.
+pub fn shadowing(alpha: f64, cos_view: f64, cos_light: f64) -> f64 {
+    if cos_view <= 0.0 || cos_light <= 0.0 {
+        return 0.0;
+    }
+    1.0 / (1.0 + lambda(alpha, cos_view) + lambda(alpha, cos_light))
+}
+
+fn lambda(alpha: f64, cos: f64) -> f64 {
+    let cos2 = cos * cos;
+    0.5 * ((1.0 + alpha * alpha * (1.0 - cos2) / cos2).sqrt() - 1.0)
+}
+
+// Schlick's approximation of the Fresnel reflectance at an angle whose cosine
+// is `cos`, from the reflectance `f0` at normal incidence.
+pub fn schlick(f0: Color, cos: f64) -> Color {
+    let weight = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 + weight * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+// Samples a microfacet normal from those visible from `view`, a unit vector in
+// the local frame above the surface, weighted by how much they face it, after
+// Heitz's "Sampling the GGX Distribution of Visible Normals" (2018).
+pub fn sample_visible_normal(view: &Vec3, alpha: f64, (u1, u2): (f64, f64)) -> Vec3 {
+    // The view from the hemisphere that the microfacets are stretched from.
+    let stretched = Vec3::new(alpha * view.x, alpha * view.y, view.z).unit_vector();
+    let length_squared = stretched.x * stretched.x + stretched.y * stretched.y;
+    let t1 = if length_squared > 0.0 {
+        Vec3::new(-stretched.y, stretched.x, 0.0) / length_squared.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = stretched.cross(&t1);
+    // A point on the disk, squeezed onto the part of it that projects the
+    // visible half of the hemisphere.
+    let radius = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = radius * phi.cos();
+    let s = 0.5 * (1.0 + stretched.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * radius * phi.sin();
+    let height = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    let normal = p1 * t1 + p2 * t2 + height * stretched;
+    Vec3::new(alpha * normal.x, alpha * normal.y, normal.z.max(0.0)).unit_vector()
+}
+
+// The density with respect to solid angle of the reflections of `view` off
+// visible normals sampled by `sample_visible_normal` reaching `direction`, both
+// unit vectors in the local frame.
+pub fn reflection_pdf(view: &Vec3, direction: &Vec3, alpha: f64) -> f64 {
+    let half = *view + *direction;
+    if view.z <= 0.0 || half.near_zero() {
+        return 0.0;
+    }
+    let half = half.unit_vector();
+    if view.dot(&half) <= 0.0 {
+        return 0.0;
+    }
+    masking(alpha, view.z) * distribution(alpha, half.z) / (4.0 * view.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{IndependentSampler, Sampler};
+
+    #[test]
+    fn test_distribution_projects_to_one() {
+        // The projected areas of the microfacets add up to the surface's.
+        for alpha in [0.1, 0.5, 1.0] {
+            let steps = 100_000;
+            let integral = (0..steps)
+                .map(|step| {
+                    let cos = (step as f64 + 0.5) / steps as f64;
+                    2.0 * PI * distribution(alpha, cos) * cos / steps as f64
+                })
+                .sum::<f64>();
+            assert!((integral - 1.0).abs() < 1e-3, "{alpha}: {integral}");
+        }
+    }
+
+    #[test]
+    fn test_reflection_pdf_matches_samples() {
+        // Estimates the integral of the density over the sphere from uniform
+        // directions, which is the fraction of reflections above the surface.
+        let alpha = 0.5;
+        let view = Vec3::new(0.6, 0.0, 0.8);
+        let mut sampler = IndependentSampler::new(1);
+        let samples = 200_000;
+        let integral = (0..samples)
+            .map(|_| {
+                let direction = Vec3::random_unit_vector(&mut sampler);
+                4.0 * PI * reflection_pdf(&view, &direction, alpha)
+            })
+            .sum::<f64>()
+            / samples as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{integral}");
+        for _ in 0..1000 {
+            let normal = sample_visible_normal(&view, alpha, sampler.random_2d());
+            assert!(normal.z >= 0.0 && view.dot(&normal) >= 0.0);
+            assert!((normal.length() - 1.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,600 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+
+use image::ImageError;
+
+use crate::{
+    color::Color,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    texture::ImageTexture,
+    triangle_mesh::TriangleMesh,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        file: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Image {
+        file: PathBuf,
+        line: usize,
+        source: ImageError,
+    },
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { file, source } => write!(f, "{}: {source}", file.display()),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", file.display()),
+            ObjError::Image { file, line, source } => {
+                write!(
+                    f,
+                    "{}:{line}: cannot load texture: {source}",
+                    file.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+            ObjError::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+// Loads the faces of a Wavefront OBJ file as one triangle mesh per material,
+// using the materials of the MTL libraries it references.
+pub fn load_obj(file: &Path) -> Result<Arc<dyn Hittable>, ObjError> {
+    ObjParser::new(file, None).parse()
+}
+
+// Loads a Wavefront OBJ file, ignoring its MTL libraries and using `material`
+// for every face.
+pub fn load_obj_with_material(
+    file: &Path,
+    material: Arc<dyn Material>,
+) -> Result<Arc<dyn Hittable>, ObjError> {
+    ObjParser::new(file, Some(material)).parse()
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: HashMap<FaceVertex, usize>,
+    face_vertices: Vec<FaceVertex>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl MeshBuilder {
+    fn add_face(&mut self, corners: [FaceVertex; 3]) {
+        let face = corners.map(|corner| {
+            *self.vertices.entry(corner).or_insert_with(|| {
+                self.face_vertices.push(corner);
+                self.face_vertices.len() - 1
+            })
+        });
+        self.faces.push(face);
+    }
+
+    fn build(
+        self,
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+        material: Arc<dyn Material>,
+    ) -> TriangleMesh {
+        let mesh_positions = self
+            .face_vertices
+            .iter()
+            .map(|vertex| positions[vertex.position])
+            .collect();
+        // Attributes are only kept when every vertex of the mesh has them.
+        let mesh_uvs = self
+            .face_vertices
+            .iter()
+            .map(|vertex| vertex.uv.map(|index| uvs[index]))
+            .collect();
+        let mesh_normals = self
+            .face_vertices
+            .iter()
+            .map(|vertex| vertex.normal.map(|index| normals[index]))
+            .collect();
+        TriangleMesh::with_attributes(mesh_positions, mesh_normals, mesh_uvs, self.faces, material)
+    }
+}
+
+struct ObjParser<'a> {
+    file: &'a Path,
+    material_override: Option<Arc<dyn Material>>,
+    positions: Vec<Point3>,
+    uvs: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    meshes: Vec<(Arc<dyn Material>, MeshBuilder)>,
+    mesh_indices: HashMap<usize, usize>,
+    current_material: Arc<dyn Material>,
+}
+
+impl<'a> ObjParser<'a> {
+    fn new(file: &'a Path, material_override: Option<Arc<dyn Material>>) -> Self {
+        let current_material = material_override
+            .clone()
+            .unwrap_or_else(|| Arc::new(Lambertian::from_albedo(Color::new(0.8, 0.8, 0.8))));
+        ObjParser {
+            file,
+            material_override,
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            materials: HashMap::new(),
+            meshes: Vec::new(),
+            mesh_indices: HashMap::new(),
+            current_material,
+        }
+    }
+
+    fn parse(mut self) -> Result<Arc<dyn Hittable>, ObjError> {
+        let contents = read(self.file)?;
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let mut tokens = Tokens::new(line, self.file, line_number);
+            let Some(keyword) = tokens.next() else {
+                continue;
+            };
+            match keyword {
+                "v" => {
+                    let position = tokens.vec3()?;
+                    self.positions.push(position);
+                }
+                "vt" => {
+                    let u = tokens.number()?;
+                    let v = tokens.optional_number()?.unwrap_or(0.0);
+                    self.uvs.push((u, v));
+                }
+                "vn" => {
+                    let normal = tokens.vec3()?;
+                    self.normals.push(normal);
+                }
+                "f" => self.parse_face(&mut tokens)?,
+                "usemtl" => {
+                    let name = tokens.rest()?;
+                    if self.material_override.is_none() {
+                        self.current_material =
+                            self.materials.get(name).cloned().ok_or_else(|| {
+                                tokens.error(format!("unknown material `{name}`"))
+                            })?;
+                    }
+                }
+                "mtllib" => {
+                    if self.material_override.is_none() {
+                        let base_directory = self.file.parent().unwrap_or(Path::new("."));
+                        for library in tokens.rest()?.split_whitespace() {
+                            let materials = load_mtl(&base_directory.join(library))?;
+                            self.materials.extend(materials);
+                        }
+                    }
+                }
+                // Groups, objects, smoothing groups, lines and points do not
+                // affect the triangles that are rendered.
+                "o" | "g" | "s" | "l" | "p" => {}
+                _ => return Err(tokens.error(format!("unsupported statement `{keyword}`"))),
+            }
+        }
+
+        let mut objects = HittableList::new();
+        for (material, builder) in self.meshes {
+            let mesh = builder.build(&self.positions, &self.uvs, &self.normals, material);
+            objects.add(Arc::new(mesh));
+        }
+        Ok(Arc::new(objects))
+    }
+
+    fn parse_face(&mut self, tokens: &mut Tokens) -> Result<(), ObjError> {
+        let mut corners = Vec::new();
+        while let Some(token) = tokens.next() {
+            corners.push(self.parse_face_vertex(token, tokens)?);
+        }
+        if corners.len() < 3 {
+            return Err(tokens.error("a face needs at least three vertices".to_owned()));
+        }
+
+        let key = Arc::as_ptr(&self.current_material) as *const () as usize;
+        let mesh_index = *self.mesh_indices.entry(key).or_insert_with(|| {
+            self.meshes
+                .push((self.current_material.clone(), MeshBuilder::default()));
+            self.meshes.len() - 1
+        });
+        let builder = &mut self.meshes[mesh_index].1;
+        for i in 1..corners.len() - 1 {
+            builder.add_face([corners[0], corners[i], corners[i + 1]]);
+        }
+        Ok(())
+    }
+
+    fn parse_face_vertex(&self, token: &str, tokens: &Tokens) -> Result<FaceVertex, ObjError> {
+        let mut indices = token.split('/');
+        let mut resolve = |count: usize, kind: &str| -> Result<Option<usize>, ObjError> {
+            match indices.next() {
+                None | Some("") => Ok(None),
+                Some(index) => {
+                    let index: i64 = index.parse().map_err(|_| {
+                        tokens.error(format!("invalid {kind} index `{index}` in `{token}`"))
+                    })?;
+                    // Indices start at 1, negative ones count back from the
+                    // last element declared so far.
+                    let resolved = if index > 0 {
+                        index - 1
+                    } else {
+                        count as i64 + index
+                    };
+                    if index == 0 || resolved < 0 || resolved >= count as i64 {
+                        return Err(tokens.error(format!(
+                            "{kind} index {index} is out of range ({count} declared)"
+                        )));
+                    }
+                    Ok(Some(resolved as usize))
+                }
+            }
+        };
+        let position = resolve(self.positions.len(), "vertex")?
+            .ok_or_else(|| tokens.error(format!("missing vertex index in `{token}`")))?;
+        let uv = resolve(self.uvs.len(), "texture coordinate")?;
+        let normal = resolve(self.normals.len(), "normal")?;
+        Ok(FaceVertex {
+            position,
+            uv,
+            normal,
+        })
+    }
+}
+
+struct MaterialProperties {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    transmission_filter: Color,
+    illumination: u32,
+    diffuse_texture: Option<Arc<ImageTexture>>,
+}
+
+impl Default for MaterialProperties {
+    fn default() -> Self {
+        MaterialProperties {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.0,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            transmission_filter: Color::new(1.0, 1.0, 1.0),
+            illumination: 2,
+            diffuse_texture: None,
+        }
+    }
+}
+
+impl MaterialProperties {
+    // Emissive materials become lights, transparent ones glass tinted by their
+    // transmission filter, and those with a specular colour but no diffuse one
+    // (or asking for ray traced reflections) metals, whose fuzz decreases with
+    // the shininess.
+    fn build(self) -> Arc<dyn Material> {
+        let is_black = |color: &Color| color.r <= 0.0 && color.g <= 0.0 && color.b <= 0.0;
+        if !is_black(&self.emission) {
+            return Arc::new(DiffuseLight::from_color(self.emission));
+        }
+        if self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9) {
+            return Arc::new(
+                Dielectric::new(self.refraction_index).with_tint(self.transmission_filter),
+            );
+        }
+        let is_metal = !is_black(&self.specular)
+            && (self.illumination == 3
+                || (is_black(&self.diffuse) && self.diffuse_texture.is_none()));
+        if is_metal {
+            let fuzz = f64::sqrt(2.0 / (self.shininess + 2.0));
+            return Arc::new(Metal::new(self.specular, fuzz));
+        }
+        match self.diffuse_texture {
+            Some(texture) => Arc::new(Lambertian::new(texture)),
+            None => Arc::new(Lambertian::from_albedo(self.diffuse)),
+        }
+    }
+}
+
+fn load_mtl(file: &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let contents = read(file)?;
+    let base_directory = file.parent().unwrap_or(Path::new("."));
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MaterialProperties)> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let mut tokens = Tokens::new(line, file, line_number);
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            if let Some((name, properties)) = current.take() {
+                materials.insert(name, properties.build());
+            }
+            current = Some((tokens.rest()?.to_owned(), MaterialProperties::default()));
+            continue;
+        }
+        let Some((_, properties)) = current.as_mut() else {
+            return Err(tokens.error(format!("`{keyword}` before any `newmtl`")));
+        };
+        match keyword {
+            "Kd" => properties.diffuse = Color::from(tokens.vec3()?),
+            "Ks" => properties.specular = Color::from(tokens.vec3()?),
+            "Ke" => properties.emission = Color::from(tokens.vec3()?),
+            "Ns" => properties.shininess = tokens.number()?,
+            "Ni" => properties.refraction_index = tokens.number()?,
+            "d" => properties.dissolve = tokens.number()?,
+            "Tr" => properties.dissolve = 1.0 - tokens.number()?,
+            "Tf" => properties.transmission_filter = Color::from(tokens.vec3()?),
+            "illum" => {
+                let illumination = tokens.number()?;
+                properties.illumination = illumination as u32;
+            }
+            "map_Kd" => {
+                // Texture options come before the file name, which is last.
+                let name = tokens.rest()?.split_whitespace().last().unwrap_or_default();
+                let texture_file = base_directory.join(name);
+                let texture =
+                    ImageTexture::load(&texture_file).map_err(|source| ObjError::Image {
+                        file: file.to_path_buf(),
+                        line: line_number,
+                        source,
+                    })?;
+                properties.diffuse_texture = Some(Arc::new(texture));
+            }
+            // Ambient colours and other texture maps have no equivalent in the
+            // available materials.
+            _ => {}
+        }
+    }
+    if let Some((name, properties)) = current {
+        materials.insert(name, properties.build());
+    }
+    Ok(materials)
+}
+
+fn read(file: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(file).map_err(|source| ObjError::Io {
+        file: file.to_path_buf(),
+        source,
+    })
+}
+
+// The whitespace separated tokens of a line, ignoring comments, that reports
+// errors at the line's location.
+struct Tokens<'a> {
+    content: &'a str,
+    tokens: std::str::SplitWhitespace<'a>,
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a str, file: &'a Path, line_number: usize) -> Self {
+        let content = line.split('#').next().unwrap_or_default();
+        Tokens {
+            content,
+            tokens: content.split_whitespace(),
+            file,
+            line: line_number,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    // Everything after the keyword, for names that may contain spaces.
+    fn rest(&self) -> Result<&'a str, ObjError> {
+        let content = self.content.trim();
+        let rest = content
+            .split_once(char::is_whitespace)
+            .map(|(_, rest)| rest.trim())
+            .unwrap_or_default();
+        if rest.is_empty() {
+            return Err(self.error("missing name".to_owned()));
+        }
+        Ok(rest)
+    }
+
+    fn optional_number(&mut self) -> Result<Option<f64>, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(format!("invalid number `{token}`"))),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, ObjError> {
+        self.optional_number()?
+            .ok_or_else(|| self.error("missing number".to_owned()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{interval::Interval, ray::Ray, sampler::IndependentSampler};
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            env::temp_dir().join(format!("ray_tracer_obj_{name}_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (file, contents) in files {
+            fs::write(directory.join(file), contents).unwrap();
+        }
+        directory
+    }
+
+    fn down(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    const SQUARE: &str = "
+        # A unit square split in two materials
+        mtllib square.mtl
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vn 0 0 1
+        usemtl light
+        f 1/1/1 2/2/1 3/3/1
+        usemtl red
+        f -4//-1 -2//-1 -1//-1
+    ";
+
+    const SQUARE_MTL: &str = "
+        newmtl light
+        Ke 4 4 4
+
+        newmtl red
+        Kd 0.8 0.1 0.1
+    ";
+
+    #[test]
+    fn test_load_obj_with_materials() {
+        let mut sampler = IndependentSampler::new(0);
+        let directory = write_files(
+            "materials",
+            &[("square.obj", SQUARE), ("square.mtl", SQUARE_MTL)],
+        );
+        let square = load_obj(&directory.join("square.obj")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let light = square
+            .hit(&down(0.75, 0.25), &interval, &mut sampler)
+            .unwrap();
+        assert_eq!(light.material.emitted(&light), Color::new(4.0, 4.0, 4.0));
+        assert!((light.u - 0.75).abs() < 1e-9);
+        let red = square
+            .hit(&down(0.25, 0.75), &interval, &mut sampler)
+            .unwrap();
+        assert_eq!(red.material.emitted(&red), Color::default());
+        assert!(
+            square
+                .hit(&down(1.5, 0.5), &interval, &mut sampler)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_polygons_are_triangulated() {
+        let mut sampler = IndependentSampler::new(0);
+        let directory = write_files(
+            "polygon",
+            &[(
+                "quad.obj",
+                "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n",
+            )],
+        );
+        let material = Arc::new(Lambertian::from_albedo(Color::default()));
+        let quad = load_obj_with_material(&directory.join("quad.obj"), material).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let interval = Interval::new(0.001, f64::INFINITY);
+        assert!(
+            quad.hit(&down(0.75, 0.25), &interval, &mut sampler)
+                .is_some()
+        );
+        assert!(
+            quad.hit(&down(0.25, 0.75), &interval, &mut sampler)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_errors_report_file_and_line() {
+        let directory = write_files(
+            "errors",
+            &[
+                ("index.obj", "v 0 0 0\nv 1 0 0\n\nf 1 2 3\n"),
+                ("number.obj", "v 0 zero 0\n"),
+                ("material.obj", "v 0 0 0\nusemtl missing\n"),
+            ],
+        );
+        let message = |file: &str| {
+            let error = load_obj(&directory.join(file)).err().unwrap();
+            match error {
+                ObjError::Parse { line, message, .. } => (line, message),
+                _ => panic!("Expected a parse error, got {error}"),
+            }
+        };
+        assert_eq!(
+            message("index.obj"),
+            (4, "vertex index 3 is out of range (2 declared)".to_owned())
+        );
+        assert_eq!(
+            message("number.obj"),
+            (1, "invalid number `zero`".to_owned())
+        );
+        assert_eq!(
+            message("material.obj"),
+            (2, "unknown material `missing`".to_owned())
+        );
+        let error = load_obj(&directory.join("index.obj")).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .ends_with("index.obj:4: vertex index 3 is out of range (2 declared)")
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use crate::vec3::Vec3;
+
+pub struct OrthonormalBasis {
+    axis: [Vec3; 3],
+}
+
+impl OrthonormalBasis {
+    pub fn new(normal: &Vec3) -> Self {
+        let w = normal.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        OrthonormalBasis { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> &Vec3 {
+        &self.axis[0]
+    }
+
+    pub fn v(&self) -> &Vec3 {
+        &self.axis[1]
+    }
+
+    pub fn w(&self) -> &Vec3 {
+        &self.axis[2]
+    }
+
+    pub fn transform(&self, vector: &Vec3) -> Vec3 {
+        vector.x * self.axis[0] + vector.y * self.axis[1] + vector.z * self.axis[2]
+    }
+
+    // The inverse of `transform`: the coordinates of `vector` along the axes.
+    pub fn to_local(&self, vector: &Vec3) -> Vec3 {
+        Vec3::new(
+            vector.dot(&self.axis[0]),
+            vector.dot(&self.axis[1]),
+            vector.dot(&self.axis[2]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basis_is_orthonormal() {
+        let basis = OrthonormalBasis::new(&Vec3::new(1.0, 2.0, -3.0));
+        for axis in [basis.u(), basis.v(), basis.w()] {
+            assert!((axis.length() - 1.0).abs() < 1e-9);
+        }
+        assert!(basis.u().dot(basis.v()).abs() < 1e-9);
+        assert!(basis.v().dot(basis.w()).abs() < 1e-9);
+        assert!(basis.w().dot(basis.u()).abs() < 1e-9);
+        assert!((*basis.w() - Vec3::new(1.0, 2.0, -3.0).unit_vector()).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_transform_maps_z_to_w() {
+        let basis = OrthonormalBasis::new(&Vec3::new(0.0, 0.0, -2.0));
+        let transformed = basis.transform(&Vec3::new(0.0, 0.0, 1.0));
+        assert!((transformed - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+        let vector = Vec3::new(0.3, -2.0, 1.5);
+        assert!((basis.to_local(&basis.transform(&vector)) - vector).length() < 1e-9);
+    }
+}
@@ -0,0 +1,437 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds, Layer,
+    LayerAttributes, SmallVec, WritableImage,
+};
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{
+    ExtendedColorType, ImageEncoder, ImageError, ImageFormat, ImageResult, Rgb, Rgb32FImage,
+    RgbImage,
+};
+
+use crate::aov::Aov;
+use crate::color::{Color, color_to_bytes, format_color, quantize};
+use crate::framebuffer::Framebuffer;
+use crate::sampler::mix;
+use crate::tone_map::ToneMapping;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Ppm,
+    Tiff,
+    Exr,
+    Hdr,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "ppm" => Some(OutputFormat::Ppm),
+            "tif" | "tiff" => Some(OutputFormat::Tiff),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    pub fn is_high_dynamic_range(&self) -> bool {
+        matches!(self, OutputFormat::Exr | OutputFormat::Hdr)
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Ppm => ImageFormat::Pnm,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+            OutputFormat::Exr => ImageFormat::OpenExr,
+            OutputFormat::Hdr => ImageFormat::Hdr,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    UnsupportedFormat {
+        file: PathBuf,
+    },
+    Image {
+        file: PathBuf,
+        source: ImageError,
+    },
+    Exr {
+        file: PathBuf,
+        source: exr::error::Error,
+    },
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::UnsupportedFormat { file } => write!(
+                f,
+                "{}: unsupported image extension (expected .png, .jpg, .ppm, .tiff, .exr or .hdr)",
+                file.display()
+            ),
+            OutputError::Image { file, source } => write!(f, "{}: {source}", file.display()),
+            OutputError::Exr { file, source } => write!(f, "{}: {source}", file.display()),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OutputError::UnsupportedFormat { .. } => None,
+            OutputError::Image { source, .. } => Some(source),
+            OutputError::Exr { source, .. } => Some(source),
+        }
+    }
+}
+
+// Chooses the encoder from the file extension. Floating-point formats keep the
+// linear values, every other format is tone mapped, sRGB encoded and
+// quantized to 8 bits.
+pub fn write_image(
+    file: &Path,
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+) -> Result<(), OutputError> {
+    let format = OutputFormat::from_path(file).ok_or_else(|| OutputError::UnsupportedFormat {
+        file: file.to_path_buf(),
+    })?;
+    let to_bytes = |x, y, color: &Color| tone_mapping.to_bytes(color, x, y);
+    encode(file, format, framebuffer, to_bytes).map_err(|source| OutputError::Image {
+        file: file.to_path_buf(),
+        source,
+    })
+}
+
+// Writes the number of samples taken for each pixel, to check where adaptive
+// sampling spent them. Floating-point formats keep the counts, other formats
+// scale them linearly from black for none to white for the most sampled pixel.
+pub fn write_sample_counts(file: &Path, framebuffer: &Framebuffer) -> Result<(), OutputError> {
+    let format = OutputFormat::from_path(file).ok_or_else(|| OutputError::UnsupportedFormat {
+        file: file.to_path_buf(),
+    })?;
+    let counts = framebuffer.sample_counts();
+    let scale = match format.is_high_dynamic_range() {
+        true => 1.0,
+        false => 1.0 / counts.iter().copied().max().unwrap_or(0).max(1) as f64,
+    };
+    let levels = Framebuffer::from_pixels(
+        framebuffer.width(),
+        framebuffer.height(),
+        counts
+            .iter()
+            .map(|count| {
+                let level = *count as f64 * scale;
+                Color::new(level, level, level)
+            })
+            .collect(),
+        counts.to_vec(),
+    );
+    let to_bytes = |_, _, level: &Color| [(255.0 * level.r).round() as u8; 3];
+    encode(file, format, &levels, to_bytes).map_err(|source| OutputError::Image {
+        file: file.to_path_buf(),
+        source,
+    })
+}
+
+// Writes an AOV. Floating-point formats keep the values; other formats show
+// them: albedos are sRGB encoded, lighting passes tone mapped like the colour,
+// normals and UVs mapped linearly to [0, 1], depths from black at the camera
+// to white at the farthest surface, IDs as random colours and ray depths as a
+// heat map from blue to red.
+pub fn write_aov(
+    file: &Path,
+    framebuffer: &Framebuffer,
+    aov: Aov,
+    tone_mapping: &ToneMapping,
+) -> Result<(), OutputError> {
+    let format = OutputFormat::from_path(file).ok_or_else(|| OutputError::UnsupportedFormat {
+        file: file.to_path_buf(),
+    })?;
+    let maximum = framebuffer
+        .pixels()
+        .iter()
+        .fold(0.0, |maximum: f64, value| maximum.max(value.r));
+    let scale = 1.0 / maximum.max(1e-10);
+    let linear =
+        |value: Color| [value.r, value.g, value.b].map(|component| quantize(component, 0.0));
+    let to_bytes = |x, y, value: &Color| match aov {
+        Aov::Albedo => color_to_bytes(value),
+        Aov::Normal => linear(0.5 * *value + Color::new(0.5, 0.5, 0.5)),
+        Aov::Uv => linear(*value),
+        Aov::Depth => linear(scale * *value),
+        Aov::MaterialId | Aov::ObjectId => id_color(value.r as u32),
+        Aov::Emission | Aov::Direct | Aov::Indirect => tone_mapping.to_bytes(value, x, y),
+        Aov::RayDepth => linear(heat(scale * value.r)),
+    };
+    encode(file, format, framebuffer, to_bytes).map_err(|source| OutputError::Image {
+        file: file.to_path_buf(),
+        source,
+    })
+}
+
+// Writes named framebuffers as the layers of one OpenEXR file, with 32-bit
+// float R, G and B channels.
+pub fn write_exr_layers(file: &Path, layers: &[(&str, &Framebuffer)]) -> Result<(), OutputError> {
+    let layers = layers
+        .iter()
+        .map(|(name, framebuffer)| {
+            let channel = |name: &str, component: fn(&Color) -> f64| {
+                let samples = framebuffer
+                    .pixels()
+                    .iter()
+                    .map(|color| component(color) as f32)
+                    .collect();
+                AnyChannel::new(name, FlatSamples::F32(samples))
+            };
+            let channels = SmallVec::from_vec(vec![
+                channel("R", |color| color.r),
+                channel("G", |color| color.g),
+                channel("B", |color| color.b),
+            ]);
+            Layer::new(
+                (framebuffer.width() as usize, framebuffer.height() as usize),
+                LayerAttributes::named(*name),
+                Encoding::FAST_LOSSLESS,
+                AnyChannels::sort(channels),
+            )
+        })
+        .collect::<Vec<_>>();
+    let Some(first) = layers.first() else {
+        return Ok(());
+    };
+    let bounds = IntegerBounds::from_dimensions(first.size);
+    Image::from_layers(ImageAttributes::new(bounds), layers)
+        .write()
+        .to_file(file)
+        .map_err(|source| OutputError::Exr {
+            file: file.to_path_buf(),
+            source,
+        })
+}
+
+// A bright colour hashed from an ID, and black for no ID.
+fn id_color(id: u32) -> [u8; 3] {
+    if id == 0 {
+        return [0, 0, 0];
+    }
+    let hash = mix(id as u64);
+    std::array::from_fn(|channel| 64 + ((hash >> (16 * channel)) % 192) as u8)
+}
+
+// Blue, cyan, green, yellow and red for `value` from 0 to 1.
+fn heat(value: f64) -> Color {
+    let stops = [
+        Color::new(0.0, 0.0, 1.0),
+        Color::new(0.0, 1.0, 1.0),
+        Color::new(0.0, 1.0, 0.0),
+        Color::new(1.0, 1.0, 0.0),
+        Color::new(1.0, 0.0, 0.0),
+    ];
+    let position = value.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (position as usize).min(stops.len() - 2);
+    let t = position - index as f64;
+    (1.0 - t) * stops[index] + t * stops[index + 1]
+}
+
+// Writes the framebuffer as an ASCII (P3) PPM.
+pub fn write_ppm<W: Write>(framebuffer: &Framebuffer, out: &mut W) -> io::Result<()> {
+    let pixels = framebuffer
+        .pixels()
+        .iter()
+        .map(format_color)
+        .collect::<Vec<String>>()
+        .join("\n");
+    write!(
+        out,
+        "P3\n{} {}\n255\n{}",
+        framebuffer.width(),
+        framebuffer.height(),
+        pixels
+    )
+}
+
+// `to_bytes` quantizes the linear values of the pixel (x, y) for 8-bit
+// formats.
+fn encode(
+    file: &Path,
+    format: OutputFormat,
+    framebuffer: &Framebuffer,
+    to_bytes: impl Fn(u32, u32, &Color) -> [u8; 3],
+) -> ImageResult<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    if format.is_high_dynamic_range() {
+        let image = Rgb32FImage::from_fn(width, height, |x, y| {
+            let color = framebuffer.pixel(x, y);
+            Rgb([color.r as f32, color.g as f32, color.b as f32])
+        });
+        return image.save_with_format(file, format.image_format());
+    }
+
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        Rgb(to_bytes(x, y, &framebuffer.pixel(x, y)))
+    });
+    match format {
+        OutputFormat::Ppm => {
+            let writer = BufWriter::new(File::create(file)?);
+            PnmEncoder::new(writer)
+                .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                .write_image(image.as_raw(), width, height, ExtendedColorType::Rgb8)
+        }
+        _ => image.save_with_format(file, format.image_format()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::tone_map::ToneMapOperator;
+
+    fn framebuffer(width: u32, height: u32, pixels: &[Color]) -> Framebuffer {
+        Framebuffer::from_pixels(width, height, pixels.to_vec(), vec![1; pixels.len()])
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("image.PNG")),
+            Some(OutputFormat::Png)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("render/image.jpg")),
+            Some(OutputFormat::Jpeg)
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("image.exr")),
+            Some(OutputFormat::Exr)
+        );
+        assert_eq!(OutputFormat::from_path(Path::new("image")), None);
+    }
+
+    #[test]
+    fn test_write_binary_ppm() {
+        let file = env::temp_dir().join(format!("ray_tracer_output_{}.ppm", std::process::id()));
+        let pixels = [Color::new(1.0, 0.0, 0.25), Color::new(0.0, 1.0, 0.0)];
+        write_image(&file, &framebuffer(2, 1, &pixels), &ToneMapping::default()).unwrap();
+        let bytes = fs::read(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(bytes.starts_with(b"P6"));
+        assert_eq!(&bytes[bytes.len() - 6..], &[255, 0, 137, 0, 255, 0]);
+    }
+
+    #[test]
+    fn test_write_image_tone_maps() {
+        let file = env::temp_dir().join(format!("ray_tracer_tone_map_{}.ppm", std::process::id()));
+        let pixels = [Color::new(4.0, 4.0, 4.0), Color::new(1.0, 1.0, 1.0)];
+        let tone_mapping = ToneMapping {
+            exposure: -1.0,
+            operator: ToneMapOperator::Reinhard,
+            dither: false,
+        };
+        write_image(&file, &framebuffer(2, 1, &pixels), &tone_mapping).unwrap();
+        let bytes = fs::read(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        // 2 / (1 + 2) and 0.5 / (1 + 0.5), sRGB encoded.
+        assert_eq!(&bytes[bytes.len() - 6..], &[213, 213, 213, 156, 156, 156]);
+    }
+
+    #[test]
+    fn test_write_ascii_ppm() {
+        let pixels = [Color::new(1.0, 0.0, 0.25), Color::new(0.0, 1.0, 0.0)];
+        let mut out = Vec::new();
+        write_ppm(&framebuffer(2, 1, &pixels), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "P3\n2 1\n255\n255 0 137\n0 255 0"
+        );
+    }
+
+    #[test]
+    fn test_write_exr_keeps_linear_values() {
+        let file = env::temp_dir().join(format!("ray_tracer_output_{}.exr", std::process::id()));
+        let pixels = [Color::new(4.0, 0.5, 0.0)];
+        let tone_mapping = ToneMapping {
+            operator: ToneMapOperator::Aces,
+            ..ToneMapping::default()
+        };
+        write_image(&file, &framebuffer(1, 1, &pixels), &tone_mapping).unwrap();
+        let image = image::open(&file).unwrap().into_rgb32f();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(image.get_pixel(0, 0), &Rgb([4.0, 0.5, 0.0]));
+    }
+
+    #[test]
+    fn test_write_aov() {
+        let file = env::temp_dir().join(format!("ray_tracer_aov_{}.ppm", std::process::id()));
+        let normals = [Color::new(1.0, 0.0, -1.0), Color::new(0.0, 1.0, 0.0)];
+        write_aov(
+            &file,
+            &framebuffer(2, 1, &normals),
+            Aov::Normal,
+            &ToneMapping::default(),
+        )
+        .unwrap();
+        let bytes = fs::read(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(&bytes[bytes.len() - 6..], &[255, 128, 0, 128, 255, 128]);
+    }
+
+    #[test]
+    fn test_write_exr_layers() {
+        let file = env::temp_dir().join(format!("ray_tracer_layers_{}.exr", std::process::id()));
+        let beauty = framebuffer(2, 1, &[Color::new(4.0, 0.5, 0.0), Color::default()]);
+        let depth = framebuffer(2, 1, &[Color::new(3.0, 3.0, 3.0); 2]);
+        write_exr_layers(&file, &[("beauty", &beauty), ("depth", &depth)]).unwrap();
+        let image = exr::prelude::read_all_flat_layers_from_file(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        let names = image
+            .layer_data
+            .iter()
+            .map(|layer| layer.attributes.layer_name.as_ref().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["beauty", "depth"]);
+        // The channels are sorted by name: B, G, R.
+        let red = &image.layer_data[0].channel_data.list[2];
+        assert_eq!(red.sample_data.value_by_flat_index(0).to_f32(), 4.0);
+    }
+
+    #[test]
+    fn test_write_sample_counts() {
+        let file = env::temp_dir().join(format!("ray_tracer_counts_{}.ppm", std::process::id()));
+        let framebuffer =
+            Framebuffer::from_pixels(3, 1, vec![Color::default(); 3], vec![0, 16, 64]);
+        write_sample_counts(&file, &framebuffer).unwrap();
+        let bytes = fs::read(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            &bytes[bytes.len() - 9..],
+            &[0, 0, 0, 64, 64, 64, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_extension() {
+        let error = write_image(
+            Path::new("image.bmp"),
+            &Framebuffer::new(1, 1),
+            &ToneMapping::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, OutputError::UnsupportedFormat { .. }));
+    }
+}
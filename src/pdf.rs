@@ -0,0 +1,289 @@
+use std::f64::consts::PI;
+
+use crate::{
+    hittable::Hittable,
+    microfacet,
+    onb::OrthonormalBasis,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::random_unit_vector(sampler)
+    }
+}
+
+pub struct CosinePdf {
+    uvw: OrthonormalBasis,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf {
+            uvw: OrthonormalBasis::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine_theta = direction.unit_vector().dot(self.uvw.w());
+        f64::max(0.0, cosine_theta / PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.uvw.transform(&Vec3::random_cosine_direction(sampler))
+    }
+}
+
+// Directions of a mirror reflection perturbed by a random offset in a sphere
+// of radius `fuzz` around its tip, as used by fuzzy metals.
+pub struct FuzzyReflectionPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl FuzzyReflectionPdf {
+    pub fn new(reflected: &Vec3, fuzz: f64) -> Self {
+        FuzzyReflectionPdf {
+            reflected: reflected.unit_vector(),
+            fuzz,
+        }
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        // A direction is generated by the points where it crosses the sphere
+        // of offsets, each contributing its area density projected onto the
+        // unit sphere of directions.
+        let direction = direction.unit_vector();
+        let b = direction.dot(&self.reflected);
+        let discriminant = b * b - (1.0 - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let sqrtd = discriminant.sqrt();
+        let squared_distances: f64 = [b - sqrtd, b + sqrtd]
+            .iter()
+            .filter(|t| **t > 0.0)
+            .map(|t| t * t)
+            .sum();
+        squared_distances / (4.0 * PI * self.fuzz * sqrtd)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.reflected + self.fuzz * Vec3::random_unit_vector(sampler)
+    }
+}
+
+// Reflections of `view` off GGX microfacet normals sampled from the visible
+// ones, mixed with a cosine lobe for a diffuse base that is chosen with
+// probability 1 - `specular_probability`.
+pub struct MicrofacetPdf {
+    uvw: OrthonormalBasis,
+    view: Vec3,
+    alpha: f64,
+    specular_probability: f64,
+}
+
+impl MicrofacetPdf {
+    // `view` points away from the surface, towards where the light goes.
+    pub fn new(normal: &Vec3, view: &Vec3, alpha: f64, specular_probability: f64) -> Self {
+        let uvw = OrthonormalBasis::new(normal);
+        // Shading normals can face away from the viewer; the view is then
+        // taken at grazing angle.
+        let local = uvw.to_local(&view.unit_vector());
+        let view = Vec3::new(local.x, local.y, local.z.max(1e-4)).unit_vector();
+        MicrofacetPdf {
+            uvw,
+            view,
+            alpha,
+            specular_probability,
+        }
+    }
+}
+
+impl Pdf for MicrofacetPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let direction = self.uvw.to_local(&direction.unit_vector());
+        let specular = microfacet::reflection_pdf(&self.view, &direction, self.alpha);
+        let diffuse = f64::max(0.0, direction.z / PI);
+        self.specular_probability * specular + (1.0 - self.specular_probability) * diffuse
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.random() < self.specular_probability {
+            let normal =
+                microfacet::sample_visible_normal(&self.view, self.alpha, sampler.random_2d());
+            self.uvw.transform(&Vec3::reflect(&-self.view, &normal))
+        } else {
+            self.uvw.transform(&Vec3::random_cosine_direction(sampler))
+        }
+    }
+}
+
+// A coat over a base, each sampled like a microfacet surface, the coat with
+// probability `coat_probability`.
+pub struct LayeredPdf {
+    coat: MicrofacetPdf,
+    base: MicrofacetPdf,
+    coat_probability: f64,
+}
+
+impl LayeredPdf {
+    pub fn new(coat: MicrofacetPdf, base: MicrofacetPdf, coat_probability: f64) -> Self {
+        LayeredPdf {
+            coat,
+            base,
+            coat_probability,
+        }
+    }
+}
+
+impl Pdf for LayeredPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.coat_probability * self.coat.value(direction)
+            + (1.0 - self.coat_probability) * self.base.value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.random() < self.coat_probability {
+            self.coat.generate(sampler)
+        } else {
+            self.base.generate(sampler)
+        }
+    }
+}
+
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.objects.random(&self.origin, sampler)
+    }
+}
+
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        MixturePdf {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.random() < 0.5 {
+            self.pdfs[0].generate(sampler)
+        } else {
+            self.pdfs[1].generate(sampler)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{color::Color, material::Lambertian, quad::Quad, sampler::IndependentSampler};
+
+    #[test]
+    fn test_cosine_pdf_generates_in_hemisphere() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let pdf = CosinePdf::new(&normal);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = pdf.generate(&mut sampler);
+            assert!(direction.dot(&normal) >= 0.0);
+            assert!(pdf.value(&direction) >= 0.0);
+        }
+        assert_eq!(pdf.value(&Vec3::new(0.0, -1.0, 0.0)), 0.0);
+        assert!((pdf.value(&normal) - 1.0 / PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_hittable_pdf_samples_quad() {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::from_albedo(Color::default())),
+        );
+        let pdf = HittablePdf::new(&quad, Point3::new(0.0, 0.0, 0.0));
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = pdf.generate(&mut sampler);
+            assert!(pdf.value(&direction) > 0.0);
+        }
+        assert_eq!(pdf.value(&Vec3::new(0.0, 0.0, 1.0)), 0.0);
+
+        // The quad is seen head-on from the origin at distance 2, so the
+        // density towards its centre is distance^2 / area.
+        assert!((pdf.value(&Vec3::new(0.0, 0.0, -1.0)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fuzzy_reflection_pdf_covers_its_cone() {
+        // The expected inverse density of the sampled directions is the solid
+        // angle they cover, a cone of half-angle asin(fuzz) around the mirror
+        // direction.
+        let fuzz: f64 = 0.4;
+        let pdf = FuzzyReflectionPdf::new(&Vec3::new(1.0, 1.0, 0.0), fuzz);
+        let samples = 200_000;
+        let mut sampler = IndependentSampler::new(0);
+        let mean_inverse_density = (0..samples)
+            .map(|_| 1.0 / pdf.value(&pdf.generate(&mut sampler)))
+            .sum::<f64>()
+            / samples as f64;
+        let solid_angle = 2.0 * PI * (1.0 - (1.0 - fuzz * fuzz).sqrt());
+        assert!(
+            (mean_inverse_density - solid_angle).abs() < 0.01 * solid_angle,
+            "Expected {solid_angle}, got {mean_inverse_density}"
+        );
+        assert_eq!(pdf.value(&Vec3::new(-1.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_mixture_pdf_averages() {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let cosine = CosinePdf::new(&normal);
+        let mixture = MixturePdf::new(&cosine, &SpherePdf);
+        let expected = 0.5 / PI + 0.5 / (4.0 * PI);
+        assert!((mixture.value(&normal) - expected).abs() < 1e-9);
+    }
+}
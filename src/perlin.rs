@@ -1,6 +1,7 @@
-use rand::prelude::*;
-
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    sampler::IndependentSampler,
+    vec3::{Point3, Vec3},
+};
 
 const POINT_COUNT: usize = 256;
 
@@ -12,13 +13,12 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
-        let mut rng = rand::rng();
+    pub fn new(sampler: &mut IndependentSampler) -> Self {
         let random_vectors: [Vec3; POINT_COUNT] =
-            std::array::from_fn(|_| Vec3::random_unit_vector());
+            std::array::from_fn(|_| Vec3::random_unit_vector(sampler));
         let permutations: [[usize; POINT_COUNT]; 3] = std::array::from_fn(|_| {
             let mut array: [usize; POINT_COUNT] = std::array::from_fn(|i| i);
-            array.shuffle(&mut rng);
+            sampler.shuffle(&mut array);
             array
         });
         Perlin {
@@ -54,12 +54,12 @@ impl Perlin {
 
     pub fn turbulence(&self, point: &Point3, depth: i32) -> f64 {
         let mut accum = 0.0;
-        let mut loc = point.clone();
+        let mut loc = *point;
         let mut weight = 1.0;
 
         for _ in 0..depth {
             accum += weight * self.noise(&loc);
-            weight = 0.5 * weight;
+            weight *= 0.5;
             loc = 2.0 * loc;
         }
 
@@ -71,7 +71,7 @@ impl Perlin {
         let uu = fade(u);
         let vv = fade(v);
         let ww = fade(w);
-        let lerp = |t: f64, a: usize| (a as f64 * t + (1 - a) as f64 * (1.0 - t));
+        let lerp = |t: f64, a: usize| a as f64 * t + (1 - a) as f64 * (1.0 - t);
         itertools::iproduct!(0..2, 0..2, 0..2)
             .map(|(i, j, k)| {
                 let weight_vector = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
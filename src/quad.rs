@@ -7,6 +7,7 @@ use crate::{
     interval::Interval,
     material::Material,
     ray::Ray,
+    sampler::{IndependentSampler, Sampler},
     vec3::{Point3, Vec3},
 };
 
@@ -19,6 +20,7 @@ pub struct Quad {
     bbox: AABB,
     normal: Vec3,
     d: f64,
+    area: f64,
 }
 
 impl Quad {
@@ -28,6 +30,7 @@ impl Quad {
         let normal = n.unit_vector();
         let d = normal.dot(&q);
         let w = n / n.dot(&n);
+        let area = n.length();
         Quad {
             q,
             u,
@@ -37,6 +40,7 @@ impl Quad {
             bbox,
             normal,
             d,
+            area,
         }
     }
 
@@ -48,12 +52,12 @@ impl Quad {
 }
 
 impl Hittable for Quad {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
-        let denominator = self.normal.dot(&ray.direction());
+    fn hit(&self, ray: &Ray, interval: &Interval, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction());
         if denominator.abs() < 1e-8 {
             return None;
         }
-        let t = (self.d - self.normal.dot(&ray.origin())) / denominator;
+        let t = (self.d - self.normal.dot(ray.origin())) / denominator;
         if !interval.contains(t) {
             return None;
         }
@@ -82,6 +86,28 @@ impl Hittable for Quad {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(
+            &ray,
+            &Interval::new(0.001, f64::INFINITY),
+            &mut IndependentSampler::new(0),
+        ) {
+            Some(record) => {
+                let distance_squared = record.t * record.t * direction.length_squared();
+                let cosine = (direction.dot(&record.normal) / direction.length()).abs();
+                distance_squared / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.random_2d();
+        let point = self.q + (a * self.u) + (b * self.v);
+        point - *origin
+    }
 }
 
 pub fn box3d(a: Point3, b: Point3, material: Arc<dyn Material>) -> Arc<HittableList> {
@@ -1,3 +1,5 @@
+use crate::color::Color;
+use crate::spectrum::Wavelengths;
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -5,6 +7,12 @@ pub struct Ray {
     origin: Point3,
     direction: Vec3,
     time: f64,
+    // Set on the rays of spectral renders, whose colours are then the values
+    // of spectra at these wavelengths.
+    wavelengths: Option<Wavelengths>,
+    // The absorption coefficient per unit of length of the medium the ray
+    // travels through, set by the dielectrics it enters or leaves.
+    absorption: Option<Color>,
 }
 
 impl Ray {
@@ -13,9 +21,22 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelengths: None,
+            absorption: None,
         }
     }
 
+    pub fn with_wavelengths(self, wavelengths: Option<Wavelengths>) -> Self {
+        Ray {
+            wavelengths,
+            ..self
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Option<Color>) -> Self {
+        Ray { absorption, ..self }
+    }
+
     pub fn origin(&self) -> &Point3 {
         &self.origin
     }
@@ -28,6 +49,28 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelengths(&self) -> Option<&Wavelengths> {
+        self.wavelengths.as_ref()
+    }
+
+    pub fn absorption(&self) -> Option<&Color> {
+        self.absorption.as_ref()
+    }
+
+    // The fraction of each channel left after travelling to `at(scalar)`
+    // through the medium, following the Beer-Lambert law.
+    pub fn transmittance(&self, scalar: f64) -> Color {
+        let distance = scalar * self.direction.length();
+        match self.absorption {
+            Some(absorption) => Color::new(
+                (-absorption.r * distance).exp(),
+                (-absorption.g * distance).exp(),
+                (-absorption.b * distance).exp(),
+            ),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
     pub fn at(&self, scalar: f64) -> Point3 {
         self.origin + scalar * self.direction
     }
@@ -45,4 +88,13 @@ mod tests {
         let r = Ray::new(p, v, t);
         assert_eq!(r.at(2.0), Point3::new(2.0, 5.0, 4.0))
     }
+
+    #[test]
+    fn test_transmittance() {
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, 2.0), 0.0);
+        assert_eq!(r.transmittance(3.0), Color::new(1.0, 1.0, 1.0));
+        let absorbing = r.with_absorption(Some(Color::new(0.5, 0.0, 1.0)));
+        let expected = Color::new((-3.0f64).exp(), 1.0, (-6.0f64).exp());
+        assert!(absorbing.transmittance(3.0).distance(&expected) < 1e-12);
+    }
 }
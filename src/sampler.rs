@@ -0,0 +1,649 @@
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+// The source of every random number of a render. It is passed explicitly to
+// whatever needs random draws, and each pixel gets its own sampler derived
+// from the seed and the pixel, so that an image only depends on the seed and
+// not on how the pixels are distributed over threads, nor on whether the
+// render was interrupted and resumed.
+//
+// The numbers of a sample are its dimensions, handed out one after the other:
+// the first ones go to the pixel offset, the lens and the time of the camera
+// ray, and the following ones to the bounces of its path. Low-discrepancy
+// samplers spread each dimension evenly over the samples of a pixel.
+pub trait Sampler {
+    // Moves on to the sample `index` of the pixel, starting again from its
+    // first dimension.
+    fn start_sample(&mut self, index: u64);
+
+    // The next dimension of the sample, uniform in [0, 1).
+    fn random(&mut self) -> f64;
+
+    // The next two dimensions, which some samplers stratify together.
+    fn random_2d(&mut self) -> (f64, f64) {
+        let u = self.random();
+        (u, self.random())
+    }
+
+    fn random_range(&mut self, range: Range<f64>) -> f64 {
+        range.start + (range.end - range.start) * self.random()
+    }
+
+    fn random_index(&mut self, len: usize) -> usize {
+        ((self.random() * len as f64) as usize).min(len - 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    // The sampler of the pixel (`x`, `y`) of an image `width` wide.
+    // Stratified samplers stratify each block of `samples_per_pixel` samples.
+    pub fn for_pixel(
+        self,
+        seed: u64,
+        x: u32,
+        y: u32,
+        width: u32,
+        samples_per_pixel: u32,
+    ) -> Box<dyn Sampler> {
+        let pixel_index = y as u64 * width as u64 + x as u64;
+        let pixel_seed = mix(seed ^ mix(pixel_index));
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::for_pixel(seed, pixel_index)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                pixel_seed,
+                samples_per_pixel: samples_per_pixel.max(1),
+                index: 0,
+                dimension: 0,
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler {
+                pixel_seed,
+                index: 0,
+                dimension: 0,
+            }),
+            SamplerKind::Sobol => Box::new(SobolSampler {
+                pixel_seed,
+                index: 0,
+                dimension: 0,
+            }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler {
+                seed: mix(seed),
+                pixel_seed,
+                x,
+                y,
+                index: 0,
+                dimension: 0,
+            }),
+        }
+    }
+}
+
+// Uniform random numbers, which are also used to generate scenes. Each sample
+// of a pixel gets its own stream, seeded with its index, so that it does not
+// depend on how the samples before it were split into passes. The streams are
+// SplitMix64 sequences, which give the same numbers on every platform and with
+// every version of the dependencies.
+pub struct IndependentSampler {
+    state: u64,
+    seed: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler { state: seed, seed }
+    }
+
+    pub fn for_pixel(seed: u64, pixel_index: u64) -> Self {
+        IndependentSampler::new(mix(seed ^ mix(pixel_index)))
+    }
+
+    // A Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            items.swap(index, self.random_index(index + 1));
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = mix(self.state);
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        value
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, index: u64) {
+        self.state = mix(self.seed ^ mix(index));
+    }
+
+    fn random(&mut self) -> f64 {
+        to_unit(self.next_u64())
+    }
+}
+
+// Splits each dimension into `samples_per_pixel` strata, and pairs of
+// dimensions into a grid of about as many cells, and puts one jittered sample
+// in each, in an order shuffled per pixel and dimension.
+struct StratifiedSampler {
+    pixel_seed: u64,
+    samples_per_pixel: u32,
+    index: u64,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    // The position of the sample in its block of `samples_per_pixel`, the
+    // key of the strata shuffle of the block and dimension, and the key of
+    // the jitter of the sample.
+    fn stratum_keys(&self) -> (u32, u64, u64) {
+        let block_size = self.samples_per_pixel as u64;
+        let block = self.index / block_size;
+        let key = mix(self.pixel_seed ^ mix(self.dimension ^ mix(block)));
+        ((self.index % block_size) as u32, key, mix(key ^ self.index))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, index: u64) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn random(&mut self) -> f64 {
+        let (position, key, jitter) = self.stratum_keys();
+        let stratum = permute(position, self.samples_per_pixel, key as u32);
+        self.dimension += 1;
+        (stratum as f64 + to_unit(jitter)) / self.samples_per_pixel as f64
+    }
+
+    fn random_2d(&mut self) -> (f64, f64) {
+        let (position, key, jitter) = self.stratum_keys();
+        let columns = (self.samples_per_pixel as f64).sqrt() as u32;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let cell = permute(position, columns * rows, key as u32);
+        self.dimension += 2;
+        (
+            ((cell % columns) as f64 + to_unit(jitter)) / columns as f64,
+            ((cell / columns) as f64 + to_unit(mix(jitter))) / rows as f64,
+        )
+    }
+}
+
+// The Halton sequence, whose dimension `d` is the radical inverse of the
+// sample index in the `d`th prime base, with the digits permuted per pixel and
+// dimension. Later dimensions are uniform random numbers.
+struct HaltonSampler {
+    pixel_seed: u64,
+    index: u64,
+    dimension: u64,
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, index: u64) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn random(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension as usize) {
+            Some(base) => {
+                let key = mix(self.pixel_seed ^ mix(dimension));
+                scrambled_radical_inverse(*base, self.index, key)
+            }
+            None => to_unit(mix(self.pixel_seed ^ mix(dimension ^ mix(self.index)))),
+        }
+    }
+}
+
+// The digits of `index` in `base`, mirrored around the radix point, with the
+// digits at each position permuted by a permutation drawn from `key`. The
+// permutations also apply to the infinite run of leading zeros, up to the
+// precision of an f64.
+fn scrambled_radical_inverse(base: u32, mut index: u64, key: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let digit_count = (53.0 / (base as f64).log2()).ceil() as u64;
+    let mut value = 0.0;
+    let mut factor = inverse_base;
+    for position in 0..digit_count {
+        let digit = (index % base as u64) as u32;
+        let permuted = permute(digit, base, mix(key ^ position) as u32);
+        value += permuted as f64 * factor;
+        index /= base as u64;
+        factor *= inverse_base;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+// The Sobol sequence with hash-based Owen scrambling, which shuffles the
+// samples and scrambles each dimension independently per pixel. Later
+// dimensions are uniform random numbers.
+struct SobolSampler {
+    pixel_seed: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, index: u64) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn random(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension as usize > SOBOL_PARAMETERS.len() {
+            return to_unit(mix(self.pixel_seed ^ mix(dimension ^ mix(self.index))));
+        }
+        let index = nested_uniform_scramble(self.index as u32, self.pixel_seed as u32);
+        let key = mix(self.pixel_seed ^ mix(dimension)) as u32;
+        let value = nested_uniform_scramble(sobol(index, dimension as usize), key);
+        value as f64 / (1u64 << 32) as f64
+    }
+}
+
+// A Sobol sequence scrambled the same way for every pixel, whose dimensions
+// are each rotated by a value of a blue noise mask, looked up at the pixel
+// with an offset per dimension. Neighbouring pixels then get very different
+// rotations, which turns the remaining error into high-frequency noise that
+// is less visible than white noise at low sample counts.
+struct BlueNoiseSampler {
+    seed: u64,
+    pixel_seed: u64,
+    x: u32,
+    y: u32,
+    index: u64,
+    dimension: u64,
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, index: u64) {
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn random(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension as usize > SOBOL_PARAMETERS.len() {
+            return to_unit(mix(self.pixel_seed ^ mix(dimension ^ mix(self.index))));
+        }
+        let index = nested_uniform_scramble(self.index as u32, self.seed as u32);
+        let key = mix(self.seed ^ mix(dimension));
+        let value = nested_uniform_scramble(sobol(index, dimension as usize), key as u32);
+        let size = BLUE_NOISE_SIZE as u32;
+        let x = (self.x + (key >> 32) as u32 % size) % size;
+        let y = (self.y + (key >> 48) as u32 % size) % size;
+        let rotation = blue_noise_mask()[(y * size + x) as usize];
+        (value as f64 / (1u64 << 32) as f64 + rotation).fract()
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// The degree `s`, the coefficients `a` and the initial direction numbers `m`
+// of the primitive polynomials of the Sobol dimensions after the first, from
+// Joe and Kuo's new-joe-kuo-6.21201 table.
+const SOBOL_PARAMETERS: [(u32, u32, &[u32]); 15] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+];
+
+// The 32 direction numbers of each Sobol dimension, the first being the van
+// der Corput sequence.
+fn sobol_directions() -> &'static [[u32; 32]] {
+    static DIRECTIONS: OnceLock<Vec<[u32; 32]>> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let mut directions = vec![std::array::from_fn(|bit| 1 << (31 - bit))];
+        for (degree, coefficients, initial) in SOBOL_PARAMETERS {
+            let s = degree as usize;
+            let mut v = [0u32; 32];
+            for bit in 0..32 {
+                v[bit] = if bit < s {
+                    initial[bit] << (31 - bit)
+                } else {
+                    let mut value = v[bit - s] ^ (v[bit - s] >> s);
+                    for k in 1..s {
+                        if (coefficients >> (s - 1 - k)) & 1 == 1 {
+                            value ^= v[bit - k];
+                        }
+                    }
+                    value
+                };
+            }
+            directions.push(v);
+        }
+        directions
+    })
+}
+
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let directions = &sobol_directions()[dimension];
+    (0..32)
+        .filter(|bit| index & (1 << bit) != 0)
+        .fold(0, |value, bit| value ^ directions[bit])
+}
+
+// Burley's hash-based Owen scrambling: a random permutation of the binary
+// digits of `value` in which each digit is flipped depending on the digits
+// above it, so that the stratification of the Sobol sequence is kept.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+// Kensler's hash-based permutation: the position of `index` in a random
+// permutation of `0..len` chosen by `key`.
+fn permute(mut index: u32, len: u32, key: u32) -> u32 {
+    let mut mask = len.saturating_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        index ^= key;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= key >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= key >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= key >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | key >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < len {
+            return index.wrapping_add(key) % len;
+        }
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// A tileable `BLUE_NOISE_SIZE` square of the values (k + 0.5) / n, for k in
+// 0..n, arranged by Ulichney's void-and-cluster method so that similar values
+// are far apart.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+fn void_and_cluster(size: usize) -> Vec<f64> {
+    let n = size * size;
+    // A Gaussian of the toroidal distance between pixels, by offset.
+    let kernel = (0..n)
+        .map(|offset| {
+            let dx = (offset % size).min(size - offset % size);
+            let dy = (offset / size).min(size - offset / size);
+            (-((dx * dx + dy * dy) as f64) / (2.0 * 1.5 * 1.5)).exp()
+        })
+        .collect::<Vec<f64>>();
+    // Sets or clears `pixel`, updating the energy of every pixel: the sum of
+    // the kernel over the set pixels.
+    let toggle = |pattern: &mut [bool], energy: &mut [f64], pixel: usize| {
+        pattern[pixel] = !pattern[pixel];
+        let sign = if pattern[pixel] { 1.0 } else { -1.0 };
+        let (x, y) = (pixel % size, pixel / size);
+        for (other, energy) in energy.iter_mut().enumerate() {
+            let dx = (other % size + size - x) % size;
+            let dy = (other / size + size - y) % size;
+            *energy += sign * kernel[dy * size + dx];
+        }
+    };
+    // The set pixel with the most energy, or the clear one with the least.
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|pixel| pattern[*pixel])
+            .max_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+            .unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|pixel| !pattern[*pixel])
+            .min_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+            .unwrap()
+    };
+
+    // A random tenth of the pixels, evened out by moving the pixels of the
+    // tightest cluster to the largest void until they are the same.
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let mut pixels = (0..n).collect::<Vec<usize>>();
+    IndependentSampler::new(0).shuffle(&mut pixels);
+    let initial_count = n / 10;
+    for pixel in &pixels[..initial_count] {
+        toggle(&mut pattern, &mut energy, *pixel);
+    }
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // The initial pixels are ranked by removing them from the tightest
+    // cluster, and the others by filling the largest void, which once more
+    // than half are set is also the tightest cluster of the clear pixels.
+    let mut ranks = vec![0; n];
+    let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&removed, &removed_energy);
+        toggle(&mut removed, &mut removed_energy, cluster);
+        ranks[cluster] = rank;
+    }
+    for rank in initial_count..n {
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        ranks[void] = rank;
+    }
+    ranks
+        .iter()
+        .map(|rank| (*rank as f64 + 0.5) / n as f64)
+        .collect()
+}
+
+// The SplitMix64 finalizer, which spreads consecutive integers over all bits.
+pub(crate) fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// The top 53 bits of `value` as a number in [0, 1).
+fn to_unit(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel_sampler(kind: SamplerKind, x: u32) -> Box<dyn Sampler> {
+        kind.for_pixel(42, x, 3, 16, 16)
+    }
+
+    // Whether `values` fall one in each of `values.len()` equal intervals.
+    fn stratified(values: impl Iterator<Item = f64>, len: usize) -> bool {
+        let mut counts = vec![0; len];
+        values.for_each(|value| counts[(value * len as f64) as usize] += 1);
+        counts.iter().all(|count| *count == 1)
+    }
+
+    #[test]
+    fn test_independent_streams_are_splitmix64() {
+        // The reference outputs of SplitMix64 seeded with 0, which the golden
+        // images rely on.
+        let mut sampler = IndependentSampler::new(0);
+        for expected in [
+            0xe220_a839_7b1d_cdaf,
+            0x6e78_9e6a_a1b9_65f4,
+            0x06c4_5d18_8009_454f,
+        ] {
+            assert_eq!(sampler.next_u64(), expected);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = IndependentSampler::for_pixel(42, 7);
+        let mut b = IndependentSampler::for_pixel(42, 7);
+        for _ in 0..100 {
+            assert_eq!(a.random().to_bits(), b.random().to_bits());
+        }
+    }
+
+    #[test]
+    fn test_pixels_and_seeds_get_different_streams() {
+        let first = |mut sampler: IndependentSampler, index| {
+            sampler.start_sample(index);
+            sampler.random()
+        };
+        let value = first(IndependentSampler::for_pixel(42, 7), 0);
+        assert_ne!(value, first(IndependentSampler::for_pixel(42, 8), 0));
+        assert_ne!(value, first(IndependentSampler::for_pixel(43, 7), 0));
+        assert_ne!(value, first(IndependentSampler::for_pixel(42, 7), 16));
+    }
+
+    #[test]
+    fn test_dimensions_are_stratified_over_the_samples() {
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let mut sampler = pixel_sampler(kind, 5);
+            let samples = (0..16)
+                .map(|index| {
+                    sampler.start_sample(index);
+                    (0..8).map(|_| sampler.random()).collect::<Vec<f64>>()
+                })
+                .collect::<Vec<_>>();
+            // Halton dimensions are only stratified over powers of their base.
+            let dimensions = if kind == SamplerKind::Halton { 1 } else { 8 };
+            for dimension in 0..dimensions {
+                let values = samples.iter().map(|sample| sample[dimension]);
+                assert!(stratified(values, 16), "{kind:?} {dimension}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_pairs_of_dimensions_are_stratified() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = pixel_sampler(kind, 5);
+            let cells = (0..16)
+                .map(|index| {
+                    sampler.start_sample(index);
+                    let (u, v) = sampler.random_2d();
+                    (u * 4.0) as usize + 4 * (v * 4.0) as usize
+                })
+                .collect::<Vec<usize>>();
+            assert!(stratified(cells.iter().map(|cell| *cell as f64 / 16.0), 16));
+        }
+    }
+
+    #[test]
+    fn test_samplers_decorrelate_pixels_and_restart_samples() {
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ] {
+            let (mut a, mut b) = (pixel_sampler(kind, 5), pixel_sampler(kind, 6));
+            let first = a.random();
+            assert_ne!(first, b.random(), "{kind:?}");
+            a.random();
+            a.start_sample(0);
+            assert_eq!(a.random(), first, "{kind:?}");
+            for dimension in 0..100 {
+                let value = a.random();
+                assert!((0.0..1.0).contains(&value), "{kind:?} {dimension}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_permute_is_a_permutation() {
+        for len in [1, 2, 3, 16, 100] {
+            let mut values = (0..len)
+                .map(|i| permute(i, len, 1234))
+                .collect::<Vec<u32>>();
+            values.sort();
+            assert_eq!(values, (0..len).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn test_blue_noise_mask() {
+        let mask = blue_noise_mask();
+        let mut values = mask.to_vec();
+        values.sort_by(f64::total_cmp);
+        let n = mask.len();
+        assert!(
+            values
+                .iter()
+                .enumerate()
+                .all(|(k, value)| *value == (k as f64 + 0.5) / n as f64)
+        );
+        // Neighbours of white noise differ by 1/3 on average, and those of
+        // blue noise by more.
+        let size = BLUE_NOISE_SIZE;
+        let difference = (0..n)
+            .map(|pixel| (mask[pixel] - mask[(pixel / size) * size + (pixel + 1) % size]).abs())
+            .sum::<f64>()
+            / n as f64;
+        assert!(difference > 0.4, "{difference}");
+    }
+}
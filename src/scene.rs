@@ -2,19 +2,42 @@ use std::env;
 use std::sync::Arc;
 
 use clap::ValueEnum;
-use rand::Rng;
 
-use crate::bvh::BVHNode;
+use crate::bvh::{BVHNode, BVHStats, SplitStrategy};
 use crate::color::Color;
 use crate::constant_medium::ConstantMedium;
-use crate::hittable::{RotateY, Translate};
+use crate::hittable::IdentifiedObject;
 use crate::hittable_list::HittableList;
 use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::matrix::Matrix4;
 use crate::quad::{Quad, box3d};
+use crate::sampler::{IndependentSampler, Sampler};
 use crate::sphere::Sphere;
 use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
+use crate::transform::Transform;
 use crate::vec3::{Point3, Vec3};
 
+pub struct SceneObjects {
+    pub world: HittableList,
+    pub lights: HittableList,
+    // The BVHs built for the scene, by name, to compare split strategies.
+    pub bvh_stats: Vec<(String, BVHStats)>,
+}
+
+impl SceneObjects {
+    pub fn new(world: HittableList, lights: HittableList) -> Self {
+        SceneObjects {
+            world,
+            lights,
+            bvh_stats: Vec::new(),
+        }
+    }
+
+    pub fn without_lights(world: HittableList) -> Self {
+        SceneObjects::new(world, HittableList::new())
+    }
+}
+
 #[derive(Clone, Debug, ValueEnum)]
 pub enum Scene {
     BouncingSpheres,
@@ -29,17 +52,23 @@ pub enum Scene {
 }
 
 impl Scene {
-    pub fn build(&self) -> HittableList {
-        match self {
-            Scene::BouncingSpheres => bouncing_spheres(),
+    // `seed` drives the random placement of objects and noise textures.
+    pub fn build(&self, strategy: SplitStrategy, seed: u64) -> SceneObjects {
+        let sampler = &mut IndependentSampler::new(seed);
+        let objects = match self {
+            Scene::BouncingSpheres => bouncing_spheres(strategy, sampler),
             Scene::CheckeredSpheres => checkered_spheres(),
             Scene::Earth => earth(),
-            Scene::PerlinSpheres => perlin_spheres(),
+            Scene::PerlinSpheres => perlin_spheres(sampler),
             Scene::Quads => quads(),
-            Scene::SimpleLight => simple_light(),
+            Scene::SimpleLight => simple_light(sampler),
             Scene::CornellBox => cornell_box(),
             Scene::CornellSmoke => cornell_smoke(),
-            Scene::FinalScene => final_scene(),
+            Scene::FinalScene => final_scene(strategy, sampler),
+        };
+        SceneObjects {
+            world: identify_objects(objects.world),
+            ..objects
         }
     }
 
@@ -58,7 +87,16 @@ impl Scene {
     }
 }
 
-fn bouncing_spheres() -> HittableList {
+// Numbers the top-level objects from 1 for the object ID AOV.
+fn identify_objects(world: HittableList) -> HittableList {
+    let mut identified = HittableList::new();
+    for (index, object) in world.objects.into_iter().enumerate() {
+        identified.add(Arc::new(IdentifiedObject::new(object, index as u32 + 1)));
+    }
+    identified
+}
+
+fn bouncing_spheres(strategy: SplitStrategy, sampler: &mut IndependentSampler) -> SceneObjects {
     let mut world = HittableList::new();
 
     let ground_material = Arc::new(Lambertian::from_albedo(Color::new(0.5, 0.5, 0.5)));
@@ -68,24 +106,23 @@ fn bouncing_spheres() -> HittableList {
         ground_material,
     )));
 
-    let mut rng = rand::rng();
     for a in -11..11 {
         for b in -11..11 {
-            let choose_material: f64 = rng.random_range(0.0..1.0);
+            let choose_material: f64 = sampler.random();
             let center = Point3::new(
-                a as f64 + 0.9 * rng.random_range(0.0..1.0),
+                a as f64 + 0.9 * sampler.random(),
                 0.2,
-                b as f64 + 0.9 * rng.random_range(0.0..1.0),
+                b as f64 + 0.9 * sampler.random(),
             );
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_material < 0.8 {
-                    let albedo = Color::random() * Color::random();
+                    let albedo = Color::random(sampler) * Color::random(sampler);
                     let material = Arc::new(Lambertian::from_albedo(albedo));
-                    let end_center = center + Vec3::new(0.0, rng.random_range(0.0..0.5), 0.0);
+                    let end_center = center + Vec3::new(0.0, sampler.random_range(0.0..0.5), 0.0);
                     world.add(Arc::new(Sphere::moving(center, end_center, 0.2, material)));
                 } else if choose_material < 0.95 {
-                    let albedo = Color::random();
-                    let fuzz = rng.random_range(0.0..0.5);
+                    let albedo = Color::random(sampler);
+                    let fuzz = sampler.random_range(0.0..0.5);
                     let material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Arc::new(Sphere::stationary(center, 0.2, material)));
                 } else {
@@ -115,12 +152,17 @@ fn bouncing_spheres() -> HittableList {
         material3,
     )));
 
+    let bvh = BVHNode::with_strategy(&mut world, strategy);
+    let bvh_stats = vec![("spheres".to_owned(), *bvh.stats())];
     let mut scene = HittableList::new();
-    scene.add(Arc::new(BVHNode::new(&mut world)));
-    scene
+    scene.add(Arc::new(bvh));
+    SceneObjects {
+        bvh_stats,
+        ..SceneObjects::without_lights(scene)
+    }
 }
 
-fn checkered_spheres() -> HittableList {
+fn checkered_spheres() -> SceneObjects {
     let mut world = HittableList::new();
     let checker = Arc::new(CheckerTexture::from_colors(
         0.32,
@@ -137,10 +179,10 @@ fn checkered_spheres() -> HittableList {
         10.0,
         Arc::new(Lambertian::new(checker)),
     )));
-    world
+    SceneObjects::without_lights(world)
 }
 
-fn earth() -> HittableList {
+fn earth() -> SceneObjects {
     let mut world = HittableList::new();
     let earth_filename = env::var("EARTH_IMAGE_PATH").unwrap();
     let earth_texture = Arc::new(ImageTexture::new(earth_filename));
@@ -151,12 +193,12 @@ fn earth() -> HittableList {
         earth_surface,
     ));
     world.add(globe);
-    world
+    SceneObjects::without_lights(world)
 }
 
-fn perlin_spheres() -> HittableList {
+fn perlin_spheres(sampler: &mut IndependentSampler) -> SceneObjects {
     let mut world = HittableList::new();
-    let perlin_texture = Arc::new(NoiseTexture::new(4.0));
+    let perlin_texture = Arc::new(NoiseTexture::new(4.0, sampler));
     world.add(Arc::new(Sphere::stationary(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -167,10 +209,10 @@ fn perlin_spheres() -> HittableList {
         2.0,
         Arc::new(Lambertian::new(perlin_texture)),
     )));
-    world
+    SceneObjects::without_lights(world)
 }
 
-fn quads() -> HittableList {
+fn quads() -> SceneObjects {
     let mut world = HittableList::new();
     let left_red = Arc::new(Lambertian::from_albedo(Color::new(1.0, 0.2, 0.2)));
     let back_green = Arc::new(Lambertian::from_albedo(Color::new(0.2, 1.0, 0.2)));
@@ -207,13 +249,13 @@ fn quads() -> HittableList {
         Vec3::new(0.0, 0.0, -4.0),
         lower_teal,
     )));
-    world
+    SceneObjects::without_lights(world)
 }
 
-fn simple_light() -> HittableList {
+fn simple_light(sampler: &mut IndependentSampler) -> SceneObjects {
     let mut world = HittableList::new();
 
-    let perlin_texture = Arc::new(NoiseTexture::new(4.0));
+    let perlin_texture = Arc::new(NoiseTexture::new(4.0, sampler));
     world.add(Arc::new(Sphere::stationary(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -225,23 +267,28 @@ fn simple_light() -> HittableList {
         Arc::new(Lambertian::new(perlin_texture)),
     )));
 
+    let mut lights = HittableList::new();
     let diffuse_light = Arc::new(DiffuseLight::from_color(Color::new(4.0, 4.0, 4.0)));
-    world.add(Arc::new(Sphere::stationary(
+    let sphere_light = Arc::new(Sphere::stationary(
         Point3::new(0.0, 7.0, 0.0),
         2.0,
         diffuse_light.clone(),
-    )));
-    world.add(Arc::new(Quad::new(
+    ));
+    world.add(sphere_light.clone());
+    lights.add(sphere_light);
+    let quad_light = Arc::new(Quad::new(
         Point3::new(3.0, 1.0, -2.0),
         Vec3::new(2.0, 0.0, 0.0),
         Vec3::new(0.0, 2.0, 0.0),
         diffuse_light,
-    )));
+    ));
+    world.add(quad_light.clone());
+    lights.add(quad_light);
 
-    world
+    SceneObjects::new(world, lights)
 }
 
-fn cornell_box() -> HittableList {
+fn cornell_box() -> SceneObjects {
     let mut world = HittableList::new();
     let red = Arc::new(Lambertian::from_albedo(Color::new(0.65, 0.05, 0.05)));
     let white = Arc::new(Lambertian::from_albedo(Color::new(0.73, 0.73, 0.73)));
@@ -259,12 +306,15 @@ fn cornell_box() -> HittableList {
         Vec3::new(0.0, 0.0, 555.0),
         red,
     )));
-    world.add(Arc::new(Quad::new(
+    let light_quad = Arc::new(Quad::new(
         Point3::new(343.0, 554.0, 332.0),
         Vec3::new(-130.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -105.0),
         light,
-    )));
+    ));
+    world.add(light_quad.clone());
+    let mut lights = HittableList::new();
+    lights.add(light_quad);
     world.add(Arc::new(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
@@ -289,8 +339,9 @@ fn cornell_box() -> HittableList {
         Point3::new(165.0, 330.0, 165.0),
         white.clone(),
     );
-    let rotated_box1 = Arc::new(RotateY::new(box1, 15.0));
-    let translated_box1 = Arc::new(Translate::new(rotated_box1, Vec3::new(265.0, 0.0, 295.0)));
+    let translated_box1 = Arc::new(
+        Transform::rotate_y(box1, 15.0).then(Matrix4::translation(Vec3::new(265.0, 0.0, 295.0))),
+    );
     world.add(translated_box1);
 
     let box2 = box3d(
@@ -298,14 +349,15 @@ fn cornell_box() -> HittableList {
         Point3::new(165.0, 165.0, 165.0),
         white.clone(),
     );
-    let rotated_box2 = Arc::new(RotateY::new(box2, -18.0));
-    let translated_box2 = Arc::new(Translate::new(rotated_box2, Vec3::new(130.0, 0.0, 65.0)));
+    let translated_box2 = Arc::new(
+        Transform::rotate_y(box2, -18.0).then(Matrix4::translation(Vec3::new(130.0, 0.0, 65.0))),
+    );
     world.add(translated_box2);
 
-    world
+    SceneObjects::new(world, lights)
 }
 
-fn cornell_smoke() -> HittableList {
+fn cornell_smoke() -> SceneObjects {
     let mut world = HittableList::new();
     let red = Arc::new(Lambertian::from_albedo(Color::new(0.65, 0.05, 0.05)));
     let white = Arc::new(Lambertian::from_albedo(Color::new(0.73, 0.73, 0.73)));
@@ -323,12 +375,15 @@ fn cornell_smoke() -> HittableList {
         Vec3::new(0.0, 0.0, 555.0),
         red,
     )));
-    world.add(Arc::new(Quad::new(
+    let light_quad = Arc::new(Quad::new(
         Point3::new(113.0, 554.0, 127.0),
         Vec3::new(330.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 305.0),
         light,
-    )));
+    ));
+    world.add(light_quad.clone());
+    let mut lights = HittableList::new();
+    lights.add(light_quad);
     world.add(Arc::new(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
@@ -353,8 +408,9 @@ fn cornell_smoke() -> HittableList {
         Point3::new(165.0, 330.0, 165.0),
         white.clone(),
     );
-    let rotated_box1 = Arc::new(RotateY::new(box1, 15.0));
-    let translated_box1 = Arc::new(Translate::new(rotated_box1, Vec3::new(265.0, 0.0, 295.0)));
+    let translated_box1 = Arc::new(
+        Transform::rotate_y(box1, 15.0).then(Matrix4::translation(Vec3::new(265.0, 0.0, 295.0))),
+    );
     world.add(Arc::new(ConstantMedium::from_albedo(
         translated_box1,
         0.01,
@@ -366,18 +422,19 @@ fn cornell_smoke() -> HittableList {
         Point3::new(165.0, 165.0, 165.0),
         white.clone(),
     );
-    let rotated_box2 = Arc::new(RotateY::new(box2, -18.0));
-    let translated_box2 = Arc::new(Translate::new(rotated_box2, Vec3::new(130.0, 0.0, 65.0)));
+    let translated_box2 = Arc::new(
+        Transform::rotate_y(box2, -18.0).then(Matrix4::translation(Vec3::new(130.0, 0.0, 65.0))),
+    );
     world.add(Arc::new(ConstantMedium::from_albedo(
         translated_box2,
         0.01,
         Color::new(1.0, 1.0, 1.0),
     )));
 
-    world
+    SceneObjects::new(world, lights)
 }
 
-fn final_scene() -> HittableList {
+fn final_scene(strategy: SplitStrategy, sampler: &mut IndependentSampler) -> SceneObjects {
     let mut world = HittableList::new();
 
     let mut boxes = HittableList::new();
@@ -390,7 +447,7 @@ fn final_scene() -> HittableList {
             let z0 = -1000.0 + j as f64 * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = rand::random_range(1.0..101.0);
+            let y1 = sampler.random_range(1.0..101.0);
             let z1 = z0 + w;
             boxes.add(box3d(
                 Point3::new(x0, y0, z0),
@@ -399,15 +456,20 @@ fn final_scene() -> HittableList {
             ));
         }
     }
-    world.add(Arc::new(BVHNode::new(&mut boxes)));
+    let ground_bvh = BVHNode::with_strategy(&mut boxes, strategy);
+    let mut bvh_stats = vec![("ground boxes".to_owned(), *ground_bvh.stats())];
+    world.add(Arc::new(ground_bvh));
 
     let light = Arc::new(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0)));
-    world.add(Arc::new(Quad::new(
+    let light_quad = Arc::new(Quad::new(
         Point3::new(123.0, 554.0, 147.0),
         Vec3::new(300.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 265.0),
         light,
-    )));
+    ));
+    world.add(light_quad.clone());
+    let mut lights = HittableList::new();
+    lights.add(light_quad);
 
     let center1 = Point3::new(400.0, 400.0, 200.0);
     let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
@@ -459,7 +521,7 @@ fn final_scene() -> HittableList {
         100.0,
         earth_material,
     )));
-    let perlin_texture = Arc::new(NoiseTexture::new(0.2));
+    let perlin_texture = Arc::new(NoiseTexture::new(0.2, sampler));
     world.add(Arc::new(Sphere::stationary(
         Point3::new(220.0, 280.0, 300.0),
         80.0,
@@ -471,15 +533,20 @@ fn final_scene() -> HittableList {
     let number_of_spheres = 1000;
     for _ in 0..number_of_spheres {
         boxes.add(Arc::new(Sphere::stationary(
-            Point3::random() * 165.0,
+            Point3::random(sampler) * 165.0,
             10.0,
             white.clone(),
         )));
     }
-    world.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(Arc::new(BVHNode::new(&mut boxes)), 15.0)),
-        Vec3::new(-100.0, 270.0, 395.0),
-    )));
+    let spheres_bvh = BVHNode::with_strategy(&mut boxes, strategy);
+    bvh_stats.push(("spheres".to_owned(), *spheres_bvh.stats()));
+    world.add(Arc::new(
+        Transform::rotate_y(Arc::new(spheres_bvh), 15.0)
+            .then(Matrix4::translation(Vec3::new(-100.0, 270.0, 395.0))),
+    ));
 
-    world
+    SceneObjects {
+        bvh_stats,
+        ..SceneObjects::new(world, lights)
+    }
 }
@@ -0,0 +1,1207 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+
+use image::ImageError;
+use serde::de::{self, MapAccess, Visitor, value::MapAccessDeserializer};
+use serde::{Deserialize, Deserializer};
+
+use crate::bvh::{BVHNode, BVHStats, SplitStrategy};
+use crate::camera::CameraSettings;
+use crate::color::Color;
+use crate::constant_medium::ConstantMedium;
+use crate::hittable::{Hittable, IdentifiedObject};
+use crate::hittable_list::HittableList;
+use crate::material::{
+    Dielectric, DiffuseLight, IdentifiedMaterial, Isotropic, Lambertian, Material, Metal,
+    Microfacet, Principled,
+};
+use crate::matrix::Matrix4;
+use crate::obj::{ObjError, load_obj, load_obj_with_material};
+use crate::quad::{Quad, box3d};
+use crate::sampler::IndependentSampler;
+use crate::scene::SceneObjects;
+use crate::spectrum::Dispersion;
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::vec3::{Point3, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl SceneFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(SceneFormat::Json),
+            "toml" => Some(SceneFormat::Toml),
+            "yaml" | "yml" => Some(SceneFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        file: PathBuf,
+        source: io::Error,
+    },
+    UnsupportedFormat {
+        file: PathBuf,
+    },
+    Parse {
+        path: String,
+        message: String,
+    },
+    UnknownTexture {
+        path: String,
+        name: String,
+    },
+    UnknownMaterial {
+        path: String,
+        name: String,
+    },
+    CyclicTexture {
+        path: String,
+        name: String,
+    },
+    Image {
+        path: String,
+        file: PathBuf,
+        source: ImageError,
+    },
+    Obj {
+        path: String,
+        source: ObjError,
+    },
+    SingularTransform {
+        path: String,
+    },
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { source, .. } => write!(f, "{source}"),
+            SceneFileError::UnsupportedFormat { .. } => write!(
+                f,
+                "unsupported scene file extension (expected .json, .toml, .yaml or .yml)"
+            ),
+            SceneFileError::Parse { path, message } => write!(f, "{path}: {message}"),
+            SceneFileError::UnknownTexture { path, name } => {
+                write!(f, "{path}: unknown texture `{name}`")
+            }
+            SceneFileError::UnknownMaterial { path, name } => {
+                write!(f, "{path}: unknown material `{name}`")
+            }
+            SceneFileError::CyclicTexture { path, name } => {
+                write!(f, "{path}: texture `{name}` refers to itself")
+            }
+            SceneFileError::Image { path, file, source } => {
+                write!(f, "{path}: cannot load image {}: {source}", file.display())
+            }
+            SceneFileError::Obj { path, source } => write!(f, "{path}: {source}"),
+            SceneFileError::SingularTransform { path } => {
+                write!(f, "{path}: transform is not invertible")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            SceneFileError::Image { source, .. } => Some(source),
+            SceneFileError::Obj { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// A value that is either the name of an entry declared at the top level of the
+// scene file or an inline definition of that entry.
+pub enum Reference<T> {
+    Named(String),
+    Inline(Box<T>),
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Reference<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReferenceVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for ReferenceVisitor<T> {
+            type Value = Reference<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a name or an inline definition")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(Reference::Named(name.to_owned()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let definition = T::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(Reference::Inline(Box::new(definition)))
+            }
+        }
+
+        deserializer.deserialize_any(ReferenceVisitor(PhantomData))
+    }
+}
+
+// A scalar material parameter, either constant or read from the red channel of
+// a texture.
+pub enum Parameter {
+    Value(f64),
+    Texture(Reference<TextureDescription>),
+}
+
+impl<'de> Deserialize<'de> for Parameter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ParameterVisitor;
+
+        impl<'de> Visitor<'de> for ParameterVisitor {
+            type Value = Parameter;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number, a texture name or an inline texture")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                Ok(Parameter::Value(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(Parameter::Value(value as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(Parameter::Value(value as f64))
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(Parameter::Texture(Reference::Named(name.to_owned())))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let definition = TextureDescription::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(Parameter::Texture(Reference::Inline(Box::new(definition))))
+            }
+        }
+
+        deserializer.deserialize_any(ParameterVisitor)
+    }
+}
+
+// The refractive index of a dielectric, either constant or varying with the
+// wavelength.
+pub enum RefractionIndex {
+    Constant(f64),
+    Dispersive(Dispersion),
+}
+
+impl<'de> Deserialize<'de> for RefractionIndex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RefractionIndexVisitor;
+
+        impl<'de> Visitor<'de> for RefractionIndexVisitor {
+            type Value = RefractionIndex;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number or a dispersion curve")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+                Ok(RefractionIndex::Constant(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                Ok(RefractionIndex::Constant(value as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(RefractionIndex::Constant(value as f64))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let dispersion = Dispersion::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(RefractionIndex::Dispersive(dispersion))
+            }
+        }
+
+        deserializer.deserialize_any(RefractionIndexVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    SolidColor {
+        albedo: Color,
+    },
+    Checker {
+        scale: f64,
+        even: Reference<TextureDescription>,
+        odd: Reference<TextureDescription>,
+    },
+    Image {
+        path: PathBuf,
+    },
+    Noise {
+        scale: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian {
+        texture: Reference<TextureDescription>,
+    },
+    Metal {
+        albedo: Color,
+        fuzz: f64,
+    },
+    Dielectric {
+        refraction_index: RefractionIndex,
+        tint: Option<Color>,
+        absorption: Option<Color>,
+        roughness: Option<f64>,
+    },
+    DiffuseLight {
+        texture: Reference<TextureDescription>,
+    },
+    Isotropic {
+        texture: Reference<TextureDescription>,
+    },
+    Microfacet {
+        base_color: Reference<TextureDescription>,
+        roughness: Parameter,
+        metallic: Parameter,
+    },
+    // Parameters left out keep the defaults of `Principled::new`.
+    Principled {
+        base_color: Reference<TextureDescription>,
+        metallic: Option<Parameter>,
+        roughness: Option<Parameter>,
+        specular: Option<Parameter>,
+        specular_transmission: Option<Parameter>,
+        clearcoat: Option<Parameter>,
+        clearcoat_roughness: Option<Parameter>,
+        sheen: Option<Parameter>,
+        sheen_tint: Option<Parameter>,
+        refraction_index: Option<Parameter>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDescription {
+    Sphere {
+        center: Point3,
+        end_center: Option<Point3>,
+        radius: f64,
+        material: Reference<MaterialDescription>,
+    },
+    Quad {
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: Reference<MaterialDescription>,
+    },
+    Box3d {
+        a: Point3,
+        b: Point3,
+        material: Reference<MaterialDescription>,
+    },
+    Triangle {
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        material: Reference<MaterialDescription>,
+    },
+    // A Wavefront OBJ mesh, using the materials of its MTL libraries unless
+    // `material` overrides them.
+    Obj {
+        path: PathBuf,
+        material: Option<Reference<MaterialDescription>>,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        density: f64,
+        texture: Reference<TextureDescription>,
+    },
+    // `transforms` are applied to `object` in order.
+    Transform {
+        object: Box<ObjectDescription>,
+        transforms: Vec<TransformDescription>,
+    },
+    Group {
+        objects: Vec<ObjectDescription>,
+        #[serde(default)]
+        bvh: bool,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformDescription {
+    Translate { offset: Vec3 },
+    // Degrees, counterclockwise around `axis`.
+    Rotate { axis: Vec3, angle: f64 },
+    Scale { factor: Vec3 },
+    Matrix { rows: [[f64; 4]; 4] },
+}
+
+impl TransformDescription {
+    fn matrix(&self) -> Matrix4 {
+        match self {
+            TransformDescription::Translate { offset } => Matrix4::translation(*offset),
+            TransformDescription::Rotate { axis, angle } => Matrix4::rotation(*axis, *angle),
+            TransformDescription::Scale { factor } => Matrix4::scaling(*factor),
+            TransformDescription::Matrix { rows } => Matrix4::new(*rows),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub camera: CameraSettings,
+    #[serde(default)]
+    pub textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+impl SceneDescription {
+    pub fn parse(contents: &str, format: SceneFormat) -> Result<Self, SceneFileError> {
+        match format {
+            SceneFormat::Json => parse_with(&mut serde_json::Deserializer::from_str(contents)),
+            SceneFormat::Toml => parse_with(toml::Deserializer::new(contents)),
+            SceneFormat::Yaml => parse_with(serde_yaml::Deserializer::from_str(contents)),
+        }
+    }
+
+    // Top-level objects made of a `diffuse_light` material, possibly
+    // transformed, are also added to the lights that get sampled directly.
+    // Groups with `bvh` set are split with `strategy`, and noise textures are
+    // generated from `seed`.
+    pub fn build(
+        &self,
+        base_directory: &Path,
+        strategy: SplitStrategy,
+        seed: u64,
+    ) -> Result<SceneObjects, SceneFileError> {
+        let mut builder = SceneBuilder::new(self, base_directory, strategy, seed);
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        for (index, description) in self.objects.iter().enumerate() {
+            let object = builder.object(description, &format!("objects[{index}]"))?;
+            if self.is_light(description) {
+                lights.add(object.clone());
+            }
+            world.add(Arc::new(IdentifiedObject::new(object, index as u32 + 1)));
+        }
+        Ok(SceneObjects {
+            bvh_stats: builder.bvh_stats,
+            ..SceneObjects::new(world, lights)
+        })
+    }
+
+    fn is_light(&self, description: &ObjectDescription) -> bool {
+        match description {
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::Quad { material, .. }
+            | ObjectDescription::Box3d { material, .. }
+            | ObjectDescription::Triangle { material, .. } => {
+                let material = match material {
+                    Reference::Inline(material) => Some(material.as_ref()),
+                    Reference::Named(name) => self.materials.get(name),
+                };
+                matches!(material, Some(MaterialDescription::DiffuseLight { .. }))
+            }
+            ObjectDescription::Transform { object, .. } => self.is_light(object),
+            _ => false,
+        }
+    }
+}
+
+fn parse_with<'de, D>(deserializer: D) -> Result<SceneDescription, SceneFileError>
+where
+    D: Deserializer<'de>,
+    D::Error: Display,
+{
+    serde_path_to_error::deserialize(deserializer).map_err(|error| SceneFileError::Parse {
+        path: error.path().to_string(),
+        message: error.inner().to_string(),
+    })
+}
+
+pub struct SceneFile {
+    pub camera: CameraSettings,
+    pub objects: SceneObjects,
+}
+
+impl SceneFile {
+    pub fn load(file: &Path, strategy: SplitStrategy, seed: u64) -> Result<Self, SceneFileError> {
+        let format =
+            SceneFormat::from_path(file).ok_or_else(|| SceneFileError::UnsupportedFormat {
+                file: file.to_path_buf(),
+            })?;
+        let contents = fs::read_to_string(file).map_err(|source| SceneFileError::Io {
+            file: file.to_path_buf(),
+            source,
+        })?;
+        let description = SceneDescription::parse(&contents, format)?;
+        let base_directory = file.parent().unwrap_or(Path::new("."));
+        let objects = description.build(base_directory, strategy, seed)?;
+        Ok(SceneFile {
+            camera: description.camera,
+            objects,
+        })
+    }
+}
+
+struct SceneBuilder<'a> {
+    description: &'a SceneDescription,
+    base_directory: &'a Path,
+    textures: HashMap<&'a str, Arc<dyn Texture>>,
+    materials: HashMap<&'a str, Arc<dyn Material>>,
+    textures_in_progress: HashSet<&'a str>,
+    strategy: SplitStrategy,
+    bvh_stats: Vec<(String, BVHStats)>,
+    sampler: IndependentSampler,
+    material_count: u32,
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn new(
+        description: &'a SceneDescription,
+        base_directory: &'a Path,
+        strategy: SplitStrategy,
+        seed: u64,
+    ) -> Self {
+        SceneBuilder {
+            description,
+            base_directory,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            textures_in_progress: HashSet::new(),
+            strategy,
+            bvh_stats: Vec::new(),
+            sampler: IndependentSampler::new(seed),
+            material_count: 0,
+        }
+    }
+
+    fn texture(
+        &mut self,
+        reference: &'a Reference<TextureDescription>,
+        path: &str,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        let name = match reference {
+            Reference::Inline(description) => return self.build_texture(description, path),
+            Reference::Named(name) => name.as_str(),
+        };
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+        let description =
+            self.description
+                .textures
+                .get(name)
+                .ok_or_else(|| SceneFileError::UnknownTexture {
+                    path: path.to_owned(),
+                    name: name.to_owned(),
+                })?;
+        if !self.textures_in_progress.insert(name) {
+            return Err(SceneFileError::CyclicTexture {
+                path: path.to_owned(),
+                name: name.to_owned(),
+            });
+        }
+        let texture = self.build_texture(description, &format!("textures.{name}"))?;
+        self.textures_in_progress.remove(name);
+        self.textures.insert(name, texture.clone());
+        Ok(texture)
+    }
+
+    fn build_texture(
+        &mut self,
+        description: &'a TextureDescription,
+        path: &str,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        let texture: Arc<dyn Texture> = match description {
+            TextureDescription::SolidColor { albedo } => Arc::new(SolidColor::new(*albedo)),
+            TextureDescription::Checker { scale, even, odd } => {
+                let even = self.texture(even, &format!("{path}.even"))?;
+                let odd = self.texture(odd, &format!("{path}.odd"))?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDescription::Image { path: image_path } => {
+                let file = self.base_directory.join(image_path);
+                let texture =
+                    ImageTexture::load(&file).map_err(|source| SceneFileError::Image {
+                        path: format!("{path}.path"),
+                        file,
+                        source,
+                    })?;
+                Arc::new(texture)
+            }
+            TextureDescription::Noise { scale } => {
+                Arc::new(NoiseTexture::new(*scale, &mut self.sampler))
+            }
+        };
+        Ok(texture)
+    }
+
+    fn parameter(
+        &mut self,
+        parameter: &'a Parameter,
+        path: &str,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        match parameter {
+            Parameter::Value(value) => Ok(Arc::new(SolidColor::new(Color::new(
+                *value, *value, *value,
+            )))),
+            Parameter::Texture(reference) => self.texture(reference, path),
+        }
+    }
+
+    fn material(
+        &mut self,
+        reference: &'a Reference<MaterialDescription>,
+        path: &str,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        let name = match reference {
+            Reference::Inline(description) => return self.build_material(description, path),
+            Reference::Named(name) => name.as_str(),
+        };
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+        let description = self.description.materials.get(name).ok_or_else(|| {
+            SceneFileError::UnknownMaterial {
+                path: path.to_owned(),
+                name: name.to_owned(),
+            }
+        })?;
+        let material = self.build_material(description, &format!("materials.{name}"))?;
+        self.materials.insert(name, material.clone());
+        Ok(material)
+    }
+
+    fn build_material(
+        &mut self,
+        description: &'a MaterialDescription,
+        path: &str,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        let texture_path = format!("{path}.texture");
+        let material: Arc<dyn Material> = match description {
+            MaterialDescription::Lambertian { texture } => {
+                Arc::new(Lambertian::new(self.texture(texture, &texture_path)?))
+            }
+            MaterialDescription::Metal { albedo, fuzz } => Arc::new(Metal::new(*albedo, *fuzz)),
+            MaterialDescription::Dielectric {
+                refraction_index,
+                tint,
+                absorption,
+                roughness,
+            } => {
+                let mut material = match refraction_index {
+                    RefractionIndex::Constant(index) => Dielectric::new(*index),
+                    RefractionIndex::Dispersive(dispersion) => Dielectric::dispersive(*dispersion),
+                };
+                if let Some(tint) = tint {
+                    material = material.with_tint(*tint);
+                }
+                if let Some(absorption) = absorption {
+                    material = material.with_absorption(*absorption);
+                }
+                if let Some(roughness) = roughness {
+                    material = material.with_roughness(*roughness);
+                }
+                Arc::new(material)
+            }
+            MaterialDescription::DiffuseLight { texture } => {
+                Arc::new(DiffuseLight::new(self.texture(texture, &texture_path)?))
+            }
+            MaterialDescription::Isotropic { texture } => {
+                Arc::new(Isotropic::new(self.texture(texture, &texture_path)?))
+            }
+            MaterialDescription::Microfacet {
+                base_color,
+                roughness,
+                metallic,
+            } => Arc::new(Microfacet::new(
+                self.texture(base_color, &format!("{path}.base_color"))?,
+                self.parameter(roughness, &format!("{path}.roughness"))?,
+                self.parameter(metallic, &format!("{path}.metallic"))?,
+            )),
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_transmission,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+                sheen_tint,
+                refraction_index,
+            } => {
+                let mut material =
+                    Principled::new(self.texture(base_color, &format!("{path}.base_color"))?);
+                if let Some(metallic) = metallic {
+                    material = material
+                        .with_metallic(self.parameter(metallic, &format!("{path}.metallic"))?);
+                }
+                if let Some(roughness) = roughness {
+                    material = material
+                        .with_roughness(self.parameter(roughness, &format!("{path}.roughness"))?);
+                }
+                if let Some(specular) = specular {
+                    material = material
+                        .with_specular(self.parameter(specular, &format!("{path}.specular"))?);
+                }
+                if let Some(specular_transmission) = specular_transmission {
+                    material = material.with_specular_transmission(self.parameter(
+                        specular_transmission,
+                        &format!("{path}.specular_transmission"),
+                    )?);
+                }
+                if let Some(clearcoat) = clearcoat {
+                    material = material
+                        .with_clearcoat(self.parameter(clearcoat, &format!("{path}.clearcoat"))?);
+                }
+                if let Some(clearcoat_roughness) = clearcoat_roughness {
+                    material =
+                        material.with_clearcoat_roughness(self.parameter(
+                            clearcoat_roughness,
+                            &format!("{path}.clearcoat_roughness"),
+                        )?);
+                }
+                if let Some(sheen) = sheen {
+                    material =
+                        material.with_sheen(self.parameter(sheen, &format!("{path}.sheen"))?);
+                }
+                if let Some(sheen_tint) = sheen_tint {
+                    material = material.with_sheen_tint(
+                        self.parameter(sheen_tint, &format!("{path}.sheen_tint"))?,
+                    );
+                }
+                if let Some(refraction_index) = refraction_index {
+                    material = material.with_refraction_index(
+                        self.parameter(refraction_index, &format!("{path}.refraction_index"))?,
+                    );
+                }
+                Arc::new(material)
+            }
+        };
+        // Numbered in the order they are built, which follows the file.
+        self.material_count += 1;
+        Ok(Arc::new(IdentifiedMaterial::new(
+            material,
+            self.material_count,
+        )))
+    }
+
+    fn object(
+        &mut self,
+        description: &'a ObjectDescription,
+        path: &str,
+    ) -> Result<Arc<dyn Hittable>, SceneFileError> {
+        let material_path = format!("{path}.material");
+        let object: Arc<dyn Hittable> = match description {
+            ObjectDescription::Sphere {
+                center,
+                end_center,
+                radius,
+                material,
+            } => {
+                let material = self.material(material, &material_path)?;
+                match end_center {
+                    Some(end_center) => {
+                        Arc::new(Sphere::moving(*center, *end_center, *radius, material))
+                    }
+                    None => Arc::new(Sphere::stationary(*center, *radius, material)),
+                }
+            }
+            ObjectDescription::Quad { q, u, v, material } => {
+                let material = self.material(material, &material_path)?;
+                Arc::new(Quad::new(*q, *u, *v, material))
+            }
+            ObjectDescription::Box3d { a, b, material } => {
+                box3d(*a, *b, self.material(material, &material_path)?)
+            }
+            ObjectDescription::Triangle { a, b, c, material } => {
+                let material = self.material(material, &material_path)?;
+                Arc::new(Triangle::new(*a, *b, *c, material))
+            }
+            ObjectDescription::Obj {
+                path: obj_path,
+                material,
+            } => {
+                let file = self.base_directory.join(obj_path);
+                let object = match material {
+                    Some(material) => {
+                        let material = self.material(material, &material_path)?;
+                        load_obj_with_material(&file, material)
+                    }
+                    None => load_obj(&file),
+                };
+                object.map_err(|source| SceneFileError::Obj {
+                    path: format!("{path}.path"),
+                    source,
+                })?
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                texture,
+            } => {
+                let boundary = self.object(boundary, &format!("{path}.boundary"))?;
+                let texture = self.texture(texture, &format!("{path}.texture"))?;
+                Arc::new(ConstantMedium::new(boundary, *density, texture))
+            }
+            ObjectDescription::Transform { object, transforms } => {
+                let object = self.object(object, &format!("{path}.object"))?;
+                let matrix = transforms
+                    .iter()
+                    .fold(Matrix4::identity(), |matrix, transform| {
+                        transform.matrix() * matrix
+                    });
+                if matrix.inverse().is_none() {
+                    return Err(SceneFileError::SingularTransform {
+                        path: format!("{path}.transforms"),
+                    });
+                }
+                Arc::new(Transform::new(object, matrix))
+            }
+            ObjectDescription::Group { objects, bvh } => {
+                let mut group = HittableList::new();
+                for (index, object) in objects.iter().enumerate() {
+                    group.add(self.object(object, &format!("{path}.objects[{index}]"))?);
+                }
+                if *bvh {
+                    let bvh = BVHNode::with_strategy(&mut group, self.strategy);
+                    self.bvh_stats.push((path.to_owned(), *bvh.stats()));
+                    Arc::new(bvh)
+                } else {
+                    Arc::new(group)
+                }
+            }
+        };
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = r#"
+        "camera": {
+            "aspect_ratio": 1.0,
+            "image_width": 10,
+            "samples_per_pixel": 1,
+            "max_depth": 5,
+            "vertical_field_of_view": 40.0,
+            "lookfrom": { "x": 0.0, "y": 0.0, "z": 5.0 },
+            "lookat": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "view_up": { "x": 0.0, "y": 1.0, "z": 0.0 },
+            "defocus_angle": 0.0,
+            "focus_distance": 10.0,
+            "background": { "x": 0.0, "y": 0.0, "z": 0.0 }
+        }"#;
+
+    fn build_json(body: &str) -> Result<SceneObjects, SceneFileError> {
+        let contents = format!("{{{CAMERA}, {body}}}");
+        SceneDescription::parse(&contents, SceneFormat::Json)?.build(
+            Path::new("."),
+            SplitStrategy::default(),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_build_with_named_and_inline_references() {
+        let objects = build_json(
+            r#"
+            "textures": {
+                "checker": {
+                    "type": "checker",
+                    "scale": 0.5,
+                    "even": { "type": "solid_color", "albedo": { "x": 1.0, "y": 1.0, "z": 1.0 } },
+                    "odd": { "type": "noise", "scale": 4.0 }
+                }
+            },
+            "materials": {
+                "ground": { "type": "lambertian", "texture": "checker" }
+            },
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "ground"
+                },
+                {
+                    "type": "transform",
+                    "transforms": [
+                        { "type": "scale", "factor": { "x": 1.0, "y": 2.0, "z": 1.0 } },
+                        { "type": "rotate", "axis": { "x": 0.0, "y": 1.0, "z": 0.0 }, "angle": 15.0 },
+                        { "type": "translate", "offset": { "x": 1.0, "y": 0.0, "z": 0.0 } }
+                    ],
+                    "object": {
+                        "type": "box3d",
+                        "a": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                        "b": { "x": 1.0, "y": 1.0, "z": 1.0 },
+                        "material": { "type": "metal", "albedo": { "x": 0.5, "y": 0.5, "z": 0.5 }, "fuzz": 0.1 }
+                    }
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(objects.world.objects.len(), 2);
+        assert!(objects.lights.objects.is_empty());
+    }
+
+    #[test]
+    fn test_microfacet_parameters_take_numbers_and_textures() {
+        let objects = build_json(
+            r#"
+            "textures": {
+                "scratches": { "type": "noise", "scale": 8.0 }
+            },
+            "materials": {
+                "gold": {
+                    "type": "microfacet",
+                    "base_color": { "type": "solid_color", "albedo": { "x": 1.0, "y": 0.8, "z": 0.3 } },
+                    "roughness": "scratches",
+                    "metallic": 1
+                },
+                "plastic": {
+                    "type": "microfacet",
+                    "base_color": "scratches",
+                    "roughness": 0.4,
+                    "metallic": { "type": "solid_color", "albedo": { "x": 0.0, "y": 0.0, "z": 0.0 } }
+                }
+            },
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "gold"
+                },
+                {
+                    "type": "sphere",
+                    "center": { "x": 2.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "plastic"
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(objects.world.objects.len(), 2);
+        let error = build_json(
+            r#"
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": {
+                        "type": "microfacet",
+                        "base_color": { "type": "solid_color", "albedo": { "x": 1.0, "y": 1.0, "z": 1.0 } },
+                        "roughness": "missing",
+                        "metallic": 0.0
+                    }
+                }
+            ]"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "objects[0].material.roughness: unknown texture `missing`"
+        );
+    }
+
+    #[test]
+    fn test_principled_parameters_are_optional() {
+        let objects = build_json(
+            r#"
+            "materials": {
+                "car_paint": {
+                    "type": "principled",
+                    "base_color": { "type": "solid_color", "albedo": { "x": 0.6, "y": 0.05, "z": 0.05 } },
+                    "metallic": 0.3,
+                    "roughness": { "type": "noise", "scale": 4.0 },
+                    "clearcoat": 1,
+                    "clearcoat_roughness": 0.05
+                },
+                "bottle": {
+                    "type": "principled",
+                    "base_color": { "type": "solid_color", "albedo": { "x": 0.8, "y": 1.0, "z": 0.8 } },
+                    "specular_transmission": 1.0,
+                    "refraction_index": 1.45
+                },
+                "opal": {
+                    "type": "principled",
+                    "base_color": { "type": "solid_color", "albedo": { "x": 0.9, "y": 0.9, "z": 1.0 } },
+                    "specular_transmission": 0.5,
+                    "refraction_index": { "type": "solid_color", "albedo": { "x": 1.45, "y": 0.0, "z": 0.0 } }
+                }
+            },
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "car_paint"
+                },
+                {
+                    "type": "sphere",
+                    "center": { "x": 2.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "bottle"
+                },
+                {
+                    "type": "sphere",
+                    "center": { "x": 4.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": "opal"
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(objects.world.objects.len(), 3);
+        let error = build_json(
+            r#"
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": {
+                        "type": "principled",
+                        "base_color": { "type": "solid_color", "albedo": { "x": 1.0, "y": 1.0, "z": 1.0 } },
+                        "sheen": "missing"
+                    }
+                }
+            ]"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "objects[0].material.sheen: unknown texture `missing`"
+        );
+    }
+
+    #[test]
+    fn test_dielectric_takes_dispersion_curves() {
+        let objects = build_json(
+            r#"
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": {
+                        "type": "dielectric",
+                        "refraction_index": {
+                            "type": "sellmeier",
+                            "b": [1.03961212, 0.231792344, 1.01046945],
+                            "c": [0.00600069867, 0.0200179144, 103.560653]
+                        }
+                    }
+                },
+                {
+                    "type": "sphere",
+                    "center": { "x": 2.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": {
+                        "type": "dielectric",
+                        "refraction_index": { "type": "cauchy", "a": 2.38, "b": 0.0093 }
+                    }
+                }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(objects.world.objects.len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_material_reports_path() {
+        let error = build_json(
+            r#"
+            "objects": [
+                {
+                    "type": "group",
+                    "objects": [
+                        {
+                            "type": "sphere",
+                            "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                            "radius": 1.0,
+                            "material": "missing"
+                        }
+                    ]
+                }
+            ]"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "objects[0].objects[0].material: unknown material `missing`"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_reports_path() {
+        let error = build_json(r#""objects": [], "materials": { "glass": { "type": "glass" } }"#)
+            .err()
+            .unwrap();
+        match error {
+            SceneFileError::Parse { path, .. } => assert_eq!(path, "materials.glass.type"),
+            _ => panic!("Expected a parse error, got {error}"),
+        }
+    }
+
+    #[test]
+    fn test_camera_filter_is_checked() {
+        let camera = CAMERA.replace(
+            r#""defocus_angle""#,
+            r#""filter": { "type": "box", "radius": 0.3 }, "defocus_angle""#,
+        );
+        let error =
+            SceneDescription::parse(&format!("{{{camera}, \"objects\": []}}"), SceneFormat::Json)
+                .err()
+                .unwrap();
+        match error {
+            SceneFileError::Parse { path, message } => {
+                assert_eq!(path, "camera.filter");
+                assert!(message.starts_with("radius must be at least 0.5 pixels, got 0.3"));
+            }
+            _ => panic!("Expected a parse error, got {error}"),
+        }
+    }
+
+    #[test]
+    fn test_cyclic_texture_is_an_error() {
+        let error = build_json(
+            r#"
+            "textures": {
+                "a": { "type": "checker", "scale": 1.0, "even": "b", "odd": "b" },
+                "b": { "type": "checker", "scale": 1.0, "even": "a", "odd": "a" }
+            },
+            "objects": [
+                {
+                    "type": "sphere",
+                    "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "radius": 1.0,
+                    "material": { "type": "lambertian", "texture": "a" }
+                }
+            ]"#,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, SceneFileError::CyclicTexture { .. }));
+    }
+
+    #[test]
+    fn test_parse_toml_and_yaml() {
+        let toml = r#"
+            [camera]
+            aspect_ratio = 1.0
+            image_width = 10
+            samples_per_pixel = 1
+            max_depth = 5
+            vertical_field_of_view = 40.0
+            lookfrom = { x = 0.0, y = 0.0, z = 5.0 }
+            lookat = { x = 0.0, y = 0.0, z = 0.0 }
+            view_up = { x = 0.0, y = 1.0, z = 0.0 }
+            defocus_angle = 0.0
+            focus_distance = 10.0
+            background = { x = 0.0, y = 0.0, z = 0.0 }
+
+            [materials.light]
+            type = "diffuse_light"
+            texture = { type = "solid_color", albedo = { x = 4.0, y = 4.0, z = 4.0 } }
+
+            [[objects]]
+            type = "quad"
+            q = { x = 0.0, y = 0.0, z = 0.0 }
+            u = { x = 1.0, y = 0.0, z = 0.0 }
+            v = { x = 0.0, y = 1.0, z = 0.0 }
+            material = "light"
+        "#;
+        let description = SceneDescription::parse(toml, SceneFormat::Toml).unwrap();
+        let objects = description
+            .build(Path::new("."), SplitStrategy::default(), 0)
+            .unwrap();
+        assert_eq!(objects.world.objects.len(), 1);
+        assert_eq!(objects.lights.objects.len(), 1);
+
+        let yaml = r#"
+            camera:
+              aspect_ratio: 1.0
+              image_width: 10
+              samples_per_pixel: 1
+              max_depth: 5
+              vertical_field_of_view: 40.0
+              lookfrom: { x: 0.0, y: 0.0, z: 5.0 }
+              lookat: { x: 0.0, y: 0.0, z: 0.0 }
+              view_up: { x: 0.0, y: 1.0, z: 0.0 }
+              defocus_angle: 0.0
+              focus_distance: 10.0
+              background: { x: 0.0, y: 0.0, z: 0.0 }
+            objects:
+              - type: sphere
+                center: { x: 0.0, y: 0.0, z: 0.0 }
+                radius: 1.0
+                material:
+                  type: dielectric
+                  refraction_index: 1.5
+                  tint: { x: 0.8, y: 1.0, z: 0.9 }
+                  absorption: { x: 0.1, y: 0.0, z: 0.2 }
+                  roughness: 0.2
+        "#;
+        let description = SceneDescription::parse(yaml, SceneFormat::Yaml).unwrap();
+        let objects = description
+            .build(Path::new("."), SplitStrategy::default(), 0)
+            .unwrap();
+        assert_eq!(objects.world.objects.len(), 1);
+        assert!(objects.lights.objects.is_empty());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            SceneFormat::from_path(Path::new("scenes/cornell_box.JSON")),
+            Some(SceneFormat::Json)
+        );
+        assert_eq!(
+            SceneFormat::from_path(Path::new("scene.yml")),
+            Some(SceneFormat::Yaml)
+        );
+        assert_eq!(SceneFormat::from_path(Path::new("scene.txt")), None);
+    }
+}
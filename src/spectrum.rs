@@ -0,0 +1,257 @@
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{color::Color, vec3::Vec3};
+
+// The visible range that wavelengths are sampled from, in nanometres.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 780.0;
+
+// The Fraunhofer d line, where the refractive indices of glasses are usually
+// quoted, and which dispersive materials use outside of spectral rendering.
+pub const REFERENCE_WAVELENGTH: f64 = 587.56;
+
+const WAVELENGTH_RANGE: f64 = MAX_WAVELENGTH - MIN_WAVELENGTH;
+
+// The wavelengths that a spectral path carries, one per channel of the colours
+// along it, after Wilkie et al.'s "Hero Wavelength Spectral Sampling" (2014):
+// the first, the hero, is uniform over the visible range and the others are
+// spread evenly from it, wrapping around. Where the light takes a direction
+// that depends on the wavelength, only the hero follows it, and the others are
+// terminated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Wavelengths {
+    lambdas: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    pub fn sample(u: f64) -> Self {
+        let lambdas = std::array::from_fn(|index| {
+            let offset = (u + index as f64 / 3.0).fract();
+            MIN_WAVELENGTH + offset * WAVELENGTH_RANGE
+        });
+        Wavelengths {
+            lambdas,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambdas[0]
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.secondary_terminated
+    }
+
+    pub fn terminate_secondary(&self) -> Self {
+        Wavelengths {
+            secondary_terminated: true,
+            ..*self
+        }
+    }
+
+    // The weight of a scattering event that leaves only the hero where `self`
+    // had them all: it then stands for every wavelength.
+    pub fn termination_weight(&self, next: &Wavelengths) -> Color {
+        match !self.secondary_terminated && next.secondary_terminated {
+            true => Color::new(3.0, 0.0, 0.0),
+            false => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // The values at these wavelengths of the spectrum upsampled from the
+    // linear sRGB colour `rgb`, with those of terminated wavelengths zeroed.
+    pub fn spectrum(&self, rgb: &Color) -> Color {
+        let value = |index: usize| match index > 0 && self.secondary_terminated {
+            true => 0.0,
+            false => rgb_to_spectrum(rgb, self.lambdas[index]),
+        };
+        Color::new(value(0), value(1), value(2))
+    }
+
+    // The CIE XYZ tristimulus values estimated from the `values` of a
+    // spectrum at these wavelengths, with Y normalized so that a constant
+    // spectrum of 1 has a luminance of 1.
+    pub fn to_xyz(&self, values: &Color) -> Vec3 {
+        let values = [values.r, values.g, values.b];
+        self.lambdas
+            .iter()
+            .zip(values)
+            .map(|(lambda, value)| value * color_matching(*lambda))
+            .sum::<Vec3>()
+            * (WAVELENGTH_RANGE / 3.0 / cie_y_integral())
+    }
+
+    // The linear sRGB colour estimated from the `values` of a spectrum at
+    // these wavelengths, white balanced so that a constant spectrum of 1 is
+    // white.
+    pub fn to_rgb(&self, values: &Color) -> Color {
+        xyz_to_linear_srgb(&self.to_xyz(values)) / white_point()
+    }
+}
+
+// A smooth reflectance spectrum for a linear sRGB colour: a blend of blue,
+// green and red bands that add up to 1 everywhere, so that greys stay flat and
+// colours within [0, 1] stay valid reflectances.
+pub fn rgb_to_spectrum(rgb: &Color, wavelength: f64) -> f64 {
+    let blue_to_green = smoothstep(470.0, 510.0, wavelength);
+    let green_to_red = smoothstep(570.0, 610.0, wavelength);
+    rgb.b * (1.0 - blue_to_green) + rgb.g * (blue_to_green - green_to_red) + rgb.r * green_to_red
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// The CIE 1931 colour matching functions, with Wyman, Sloan and Shirley's
+// multi-lobe piecewise Gaussian fit from "Simple Analytic Approximations to the
+// CIE XYZ Color Matching Functions" (2013).
+pub fn color_matching(wavelength: f64) -> Vec3 {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let sigma = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+// Linear sRGB, with the Rec. 709 primaries and the D65 white point.
+pub fn xyz_to_linear_srgb(xyz: &Vec3) -> Color {
+    Color::new(
+        3.240_454_2 * xyz.x - 1.537_138_5 * xyz.y - 0.498_531_4 * xyz.z,
+        -0.969_266_0 * xyz.x + 1.876_010_8 * xyz.y + 0.041_556_0 * xyz.z,
+        0.055_643_4 * xyz.x - 0.204_025_9 * xyz.y + 1.057_225_2 * xyz.z,
+    )
+}
+
+// Integrals over the visible range, with the midpoint rule at 0.1 nm.
+fn integrate(f: impl Fn(f64) -> Vec3) -> Vec3 {
+    let steps = (WAVELENGTH_RANGE * 10.0) as usize;
+    let step = WAVELENGTH_RANGE / steps as f64;
+    (0..steps)
+        .map(|index| f(MIN_WAVELENGTH + (index as f64 + 0.5) * step) * step)
+        .sum()
+}
+
+fn cie_y_integral() -> f64 {
+    static INTEGRAL: OnceLock<f64> = OnceLock::new();
+    *INTEGRAL.get_or_init(|| integrate(color_matching).y)
+}
+
+// The linear sRGB colour of a constant spectrum of 1, a slightly pink white
+// since sRGB is balanced for D65 rather than an equal energy illuminant.
+fn white_point() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| xyz_to_linear_srgb(&(integrate(color_matching) / cie_y_integral())))
+}
+
+// How the refractive index of a dispersive material varies with the
+// wavelength, given in micrometres as in catalogues of glasses.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Dispersion {
+    // Cauchy's n = a + b / λ², with `b` in µm².
+    Cauchy { a: f64, b: f64 },
+    // Sellmeier's n² = 1 + Σ b λ² / (λ² - c), with `c` in µm².
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // The refractive index at `wavelength`, in nanometres.
+    pub fn refraction_index(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength / 1000.0;
+        let lambda2 = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Schott's N-BK7 borosilicate crown glass.
+    const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    #[test]
+    fn test_grey_spectra_round_trip() {
+        // Evenly spaced heroes cover the visible range like quadrature.
+        let steps = 4000;
+        let grey = Color::new(0.5, 0.5, 0.5);
+        let rgb = (0..steps)
+            .map(|step| {
+                let wavelengths = Wavelengths::sample((step as f64 + 0.5) / steps as f64);
+                wavelengths.to_rgb(&wavelengths.spectrum(&grey))
+            })
+            .sum::<Color>()
+            / steps as f64;
+        assert!(rgb.distance(&grey) < 1e-3, "{rgb:?}");
+    }
+
+    #[test]
+    fn test_primaries_keep_their_hue() {
+        let steps = 4000;
+        for (channel, primary) in [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let rgb = (0..steps)
+                .map(|step| {
+                    let wavelengths = Wavelengths::sample((step as f64 + 0.5) / steps as f64);
+                    wavelengths.to_rgb(&wavelengths.spectrum(primary))
+                })
+                .sum::<Color>()
+                / steps as f64;
+            let components = [rgb.r, rgb.g, rgb.b];
+            let strongest = (0..3)
+                .max_by(|a, b| components[*a].total_cmp(&components[*b]))
+                .unwrap();
+            assert_eq!(strongest, channel, "{rgb:?}");
+        }
+    }
+
+    #[test]
+    fn test_terminated_wavelengths_keep_the_hero() {
+        let wavelengths = Wavelengths::sample(0.25);
+        assert_eq!(wavelengths.hero(), 480.0);
+        let terminated = wavelengths.terminate_secondary();
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert_eq!(terminated.spectrum(&white), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(
+            wavelengths.termination_weight(&terminated),
+            Color::new(3.0, 0.0, 0.0)
+        );
+        assert_eq!(terminated.termination_weight(&terminated), white);
+    }
+
+    #[test]
+    fn test_dispersion_curves() {
+        assert!((BK7.refraction_index(REFERENCE_WAVELENGTH) - 1.5168).abs() < 1e-4);
+        assert!(BK7.refraction_index(450.0) > BK7.refraction_index(650.0));
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.refraction_index(500.0) - 1.516).abs() < 1e-12);
+    }
+}
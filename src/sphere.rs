@@ -5,7 +5,9 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::onb::OrthonormalBasis;
 use crate::ray::Ray;
+use crate::sampler::{IndependentSampler, Sampler};
 use crate::vec3::{Point3, Vec3};
 
 pub struct Sphere {
@@ -38,6 +40,15 @@ impl Sphere {
         }
     }
 
+    fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.random_2d();
+        let z = 1.0 + r2 * (f64::sqrt(1.0 - radius * radius / distance_squared) - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * f64::sqrt(1.0 - z * z);
+        let y = phi.sin() * f64::sqrt(1.0 - z * z);
+        Vec3::new(x, y, z)
+    }
+
     fn get_sphere_uv(&self, point: &Point3) -> (f64, f64) {
         let theta = f64::acos(-point.y);
         let phi = f64::atan2(-point.z, point.x) + PI;
@@ -51,7 +62,7 @@ impl Hittable for Sphere {
     fn bounding_box(&self) -> &AABB {
         &self.bbox
     }
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, interval: &Interval, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let current_center = self.center.at(ray.time());
         let oc: Vec3 = current_center - *ray.origin();
         let a = ray.direction().length_squared();
@@ -79,7 +90,42 @@ impl Hittable for Sphere {
         let mut record = HitRecord::new(point, normal, material, t, u, v, false);
         record.set_face_normal(ray, &normal);
 
-        return Some(record);
+        Some(record)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        if self
+            .hit(
+                &ray,
+                &Interval::new(0.001, f64::INFINITY),
+                &mut IndependentSampler::new(0),
+            )
+            .is_none()
+        {
+            return 0.0;
+        }
+        let distance_squared = (*self.center.origin() - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = f64::sqrt(1.0 - self.radius * self.radius / distance_squared);
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = *self.center.origin() - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector(sampler);
+        }
+        let uvw = OrthonormalBasis::new(&direction);
+        uvw.transform(&Sphere::random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler,
+        ))
     }
 }
 
@@ -114,6 +160,7 @@ mod tests {
 
     #[test]
     fn test_ray_hits_sphere() {
+        let mut sampler = IndependentSampler::new(0);
         let sphere = Sphere::stationary(
             Point3::new(0.0, 0.0, -1.0),
             0.5,
@@ -122,8 +169,8 @@ mod tests {
 
         let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 2.0);
         let interval = Interval::new(0.001, f64::INFINITY);
-        let result = sphere.hit(&ray, &interval);
-        assert_eq!(result.is_some(), true, "Ray should hit the sphere");
+        let result = sphere.hit(&ray, &interval, &mut sampler);
+        assert!(result.is_some(), "Ray should hit the sphere");
         let record = result.unwrap();
         assert!(
             (record.t - 0.5).abs() < 1e-6,
@@ -144,6 +191,7 @@ mod tests {
 
     #[test]
     fn test_ray_misses_sphere() {
+        let mut sampler = IndependentSampler::new(0);
         let sphere = Sphere::stationary(
             Point3::new(0.0, 0.0, -1.0),
             0.5,
@@ -152,7 +200,7 @@ mod tests {
 
         let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 2.0);
         let interval = Interval::new(0.001, f64::INFINITY);
-        let result = sphere.hit(&ray, &interval);
-        assert_eq!(result.is_none(), true, "Ray should miss the sphere");
+        let result = sphere.hit(&ray, &interval, &mut sampler);
+        assert!(result.is_none(), "Ray should miss the sphere");
     }
 }
@@ -1,8 +1,10 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use image::{DynamicImage, GenericImageView, ImageReader};
+use image::{DynamicImage, ImageReader, ImageResult};
 
-use crate::{color::Color, interval::Interval, perlin::Perlin, vec3::Point3};
+use crate::{
+    color::Color, interval::Interval, perlin::Perlin, sampler::IndependentSampler, vec3::Point3,
+};
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, point: &Point3) -> Color;
@@ -66,33 +68,59 @@ impl Texture for CheckerTexture {
     }
 }
 
+// The texels of an image, in linear sRGB. 8 and 16-bit images are taken to be
+// sRGB encoded and are decoded on load; floating-point ones, like HDR and EXR
+// files, are already linear.
 pub struct ImageTexture {
-    image: DynamicImage,
+    width: u32,
+    height: u32,
+    texels: Vec<Color>,
 }
 
 impl ImageTexture {
     pub fn new(filename: String) -> Self {
-        let image = ImageReader::open(filename).unwrap().decode().unwrap();
-        ImageTexture { image }
+        ImageTexture::load(filename).unwrap()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> ImageResult<Self> {
+        let image = ImageReader::open(path)?.decode()?;
+        Ok(ImageTexture::from_image(&image))
+    }
+
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let is_linear = matches!(
+            image,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        );
+        let texels = image
+            .to_rgb32f()
+            .pixels()
+            .map(|pixel| {
+                let color = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+                match is_linear {
+                    true => color,
+                    false => Color::from_srgb(&color),
+                }
+            })
+            .collect();
+        ImageTexture {
+            width: image.width(),
+            height: image.height(),
+            texels,
+        }
     }
 }
 
 impl Texture for ImageTexture {
     fn value(&self, u: f64, v: f64, _point: &Point3) -> Color {
-        if self.image.height() <= 0 {
+        if self.height == 0 {
             return Color::new(0.0, 1.0, 0.0);
         }
         let u = Interval::new(0.0, 1.0).clamp(u);
         let v = 1.0 - Interval::new(0.0, 1.0).clamp(v);
-        let i = (u * self.image.width() as f64) as u32;
-        let j = (v * self.image.height() as f64) as u32;
-        let pixel = self.image.get_pixel(i, j);
-        let color_scale = 1.0 / 255.0;
-        Color::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+        let i = ((u * self.width as f64) as u32).min(self.width - 1);
+        let j = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.texels[(j * self.width + i) as usize]
     }
 }
 
@@ -102,9 +130,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64, sampler: &mut IndependentSampler) -> Self {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::new(sampler),
             scale,
         }
     }
@@ -116,3 +144,25 @@ impl Texture for NoiseTexture {
             * (1.0 + f64::sin(self.scale * point.z + 10.0 * self.noise.turbulence(point, 7)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, Rgb32FImage, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn test_image_texels_are_decoded_to_linear() {
+        // Left texel black, right texel mid-grey in sRGB.
+        let image = RgbImage::from_fn(2, 1, |x, _| Rgb([128 * x as u8; 3]));
+        let texture = ImageTexture::from_image(&DynamicImage::ImageRgb8(image));
+        let point = Point3::default();
+        assert_eq!(texture.value(0.25, 0.5, &point), Color::default());
+        let grey = texture.value(1.0, 0.5, &point);
+        assert!((grey.r - 0.2158).abs() < 1e-4, "{grey:?}");
+        // Floating-point images are kept as they are.
+        let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.5, 2.0, 0.25]));
+        let texture = ImageTexture::from_image(&DynamicImage::ImageRgb32F(image));
+        assert_eq!(texture.value(0.5, 0.5, &point), Color::new(0.5, 2.0, 0.25));
+    }
+}
@@ -0,0 +1,181 @@
+use clap::ValueEnum;
+
+// The order in which the tiles of an image are handed out to the threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum TileOrder {
+    // Row by row from the top-left tile.
+    #[default]
+    Scanline,
+    // Outwards from the centre of the image, which usually matters most.
+    Spiral,
+    // Along a Hilbert curve, so that consecutive tiles are neighbours and
+    // share more of the scene in the caches.
+    Hilbert,
+}
+
+// The pixels `x0..x1` by `y0..y1` of an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        (self.x1 - self.x0) as usize * (self.y1 - self.y0) as usize
+    }
+
+    // Row-major indices of the tile's pixels in an image `width` wide.
+    pub fn pixel_indices(&self, width: u32) -> impl Iterator<Item = usize> {
+        let (x0, x1) = (self.x0, self.x1);
+        (self.y0..self.y1).flat_map(move |y| (x0..x1).map(move |x| (y * width + x) as usize))
+    }
+}
+
+// Covers a `width` by `height` image with tiles of `size` by `size` pixels,
+// smaller along the right and bottom edges, listed in `order`.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let tile = |(column, row): (u32, u32)| Tile {
+        x0: column * size,
+        y0: row * size,
+        x1: ((column + 1) * size).min(width),
+        y1: ((row + 1) * size).min(height),
+    };
+    let positions = (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row)));
+    match order {
+        TileOrder::Scanline => positions.map(tile).collect(),
+        TileOrder::Spiral => spiral(columns, rows).into_iter().map(tile).collect(),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            let mut positions = positions.collect::<Vec<(u32, u32)>>();
+            positions.sort_by_key(|(column, row)| hilbert_index(side, *column, *row));
+            positions.into_iter().map(tile).collect()
+        }
+    }
+}
+
+// Walks a square spiral from the central position, turning clockwise, and
+// keeps the positions that fall inside the grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = columns as usize * rows as usize;
+    let mut positions = Vec::with_capacity(total);
+    let (mut column, mut row) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+    while positions.len() < total {
+        let (dx, dy) = directions[leg % 4];
+        // Legs grow by one every two turns: 1, 1, 2, 2, 3, 3...
+        for _ in 0..leg / 2 + 1 {
+            if (0..columns as i64).contains(&column) && (0..rows as i64).contains(&row) {
+                positions.push((column as u32, row as u32));
+            }
+            column += dx;
+            row += dy;
+        }
+        leg += 1;
+    }
+    positions
+}
+
+// The distance along the Hilbert curve filling a `side` by `side` grid, with
+// `side` a power of two, of the position (x, y).
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotates the quadrant so that the curve continues from the last one.
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers_image_once(tiles: &[Tile], width: u32, height: u32) -> bool {
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in tiles {
+            tile.pixel_indices(width)
+                .for_each(|index| covered[index] += 1);
+        }
+        covered.iter().all(|count| *count == 1)
+    }
+
+    #[test]
+    fn test_every_order_covers_the_image() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = tiles(50, 37, 16, order);
+            assert_eq!(tiles.len(), 12);
+            assert!(covers_image_once(&tiles, 50, 37), "{order:?}");
+        }
+    }
+
+    #[test]
+    fn test_scanline_and_spiral_order() {
+        let scanline = tiles(30, 20, 10, TileOrder::Scanline);
+        assert_eq!(
+            scanline[1],
+            Tile {
+                x0: 10,
+                y0: 0,
+                x1: 20,
+                y1: 10
+            }
+        );
+        assert_eq!(
+            scanline[5],
+            Tile {
+                x0: 20,
+                y0: 10,
+                x1: 30,
+                y1: 20
+            }
+        );
+
+        let spiral = tiles(30, 30, 10, TileOrder::Spiral);
+        let positions = spiral
+            .iter()
+            .map(|tile| (tile.x0 / 10, tile.y0 / 10))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                (1, 1),
+                (2, 1),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+                (0, 1),
+                (0, 0),
+                (1, 0),
+                (2, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hilbert_tiles_are_neighbours() {
+        let tiles = tiles(64, 64, 8, TileOrder::Hilbert);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (0, 0));
+        for pair in tiles.windows(2) {
+            let distance = pair[0].x0.abs_diff(pair[1].x0) + pair[0].y0.abs_diff(pair[1].y0);
+            assert_eq!(distance, 8);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use clap::ValueEnum;
+
+use crate::{
+    color::{Color, linear_to_srgb, quantize},
+    sampler::mix,
+};
+
+// Compresses the radiance of a scene into the displayable range [0, 1].
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ToneMapOperator {
+    // Keeps the radiance, clipping what is brighter than white.
+    #[default]
+    Clamp,
+    // Reinhard's L / (1 + L) on the luminance, which keeps the hue.
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, per channel.
+    Aces,
+    // Hable's filmic curve from Uncharted 2, with a white point of 11.2.
+    Uncharted2,
+}
+
+// The post-processing of the linear framebuffer into 8-bit sRGB images: an
+// exposure compensation in stops, a tone-mapping operator, the sRGB transfer
+// function, and optionally a triangular dither of one level before
+// quantization, which breaks up the banding of smooth gradients.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToneMapping {
+    pub exposure: f64,
+    pub operator: ToneMapOperator,
+    pub dither: bool,
+}
+
+impl ToneMapping {
+    // The display-referred linear colour of a radiance, within [0, 1] but for
+    // the clamp operator.
+    pub fn apply(&self, radiance: &Color) -> Color {
+        let color = *radiance * 2.0_f64.powf(self.exposure);
+        match self.operator {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => color / (1.0 + color.luminance().max(0.0)),
+            ToneMapOperator::Aces => map_channels(&color, |x| {
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+            ToneMapOperator::Uncharted2 => {
+                let white_scale = 1.0 / uncharted2(11.2);
+                map_channels(&color, |x| uncharted2(2.0 * x) * white_scale)
+            }
+        }
+    }
+
+    // The 8-bit sRGB value of the pixel (`x`, `y`), whose position seeds the
+    // dither so that images are reproducible.
+    pub fn to_bytes(&self, radiance: &Color, x: u32, y: u32) -> [u8; 3] {
+        let color = self.apply(radiance);
+        let components = [color.r, color.g, color.b];
+        std::array::from_fn(|channel| {
+            let dither = match self.dither {
+                true => triangular_noise(x, y, channel as u64),
+                false => 0.0,
+            };
+            quantize(linear_to_srgb(components[channel]), dither)
+        })
+    }
+}
+
+fn map_channels(color: &Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(
+        f(color.r.max(0.0)),
+        f(color.g.max(0.0)),
+        f(color.b.max(0.0)),
+    )
+}
+
+fn uncharted2(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+// The sum of two uniform numbers minus one, in (-1, 1) with a triangular
+// density, hashed from the pixel and the channel.
+fn triangular_noise(x: u32, y: u32, channel: u64) -> f64 {
+    let hash = mix((((y as u64) << 32) | x as u64) ^ mix(channel));
+    let first = (hash & 0xffff_ffff) as f64 / (1u64 << 32) as f64;
+    let second = (hash >> 32) as f64 / (1u64 << 32) as f64;
+    first + second - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    #[test]
+    fn test_exposure_scales_radiance() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            ..ToneMapping::default()
+        };
+        assert_eq!(tone_mapping.apply(&grey(0.25)), grey(0.5));
+        let darker = ToneMapping {
+            exposure: -2.0,
+            ..ToneMapping::default()
+        };
+        assert_eq!(darker.apply(&grey(2.0)), grey(0.5));
+    }
+
+    #[test]
+    fn test_operators_compress_highlights() {
+        for operator in [
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::Aces,
+            ToneMapOperator::Uncharted2,
+        ] {
+            let tone_mapping = ToneMapping {
+                operator,
+                ..ToneMapping::default()
+            };
+            let map = |value| tone_mapping.apply(&grey(value)).r;
+            assert!(map(0.0).abs() < 1e-12, "{operator:?}");
+            let values = [0.01, 0.1, 0.5, 1.0, 4.0, 16.0, 1000.0].map(map);
+            assert!(
+                values.windows(2).all(|pair| pair[0] < pair[1]),
+                "{operator:?} {values:?}"
+            );
+            // Bright lights keep some detail instead of clipping to white.
+            assert!(values[4] < 0.99, "{operator:?} {values:?}");
+        }
+        let reinhard = ToneMapping {
+            operator: ToneMapOperator::Reinhard,
+            ..ToneMapping::default()
+        };
+        assert_eq!(reinhard.apply(&grey(1.0)), grey(0.5));
+    }
+
+    #[test]
+    fn test_dithering_averages_to_the_exact_value() {
+        // A value between two levels, which without dithering always rounds
+        // to the same one.
+        let radiance = grey(0.2);
+        let exact = 255.0 * linear_to_srgb(0.2);
+        let plain = ToneMapping::default();
+        let dithered = ToneMapping {
+            dither: true,
+            ..ToneMapping::default()
+        };
+        let mean = |tone_mapping: &ToneMapping| {
+            let pixels = (0..64).flat_map(|y| (0..64).map(move |x| (x, y)));
+            pixels
+                .map(|(x, y)| tone_mapping.to_bytes(&radiance, x, y)[0] as f64)
+                .sum::<f64>()
+                / 4096.0
+        };
+        assert_eq!(mean(&plain), exact.round());
+        assert!((mean(&dithered) - exact).abs() < 0.05);
+        assert!((exact - exact.round()).abs() > 0.2);
+    }
+}
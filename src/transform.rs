@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    matrix::Matrix4,
+    ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    vec3::{Point3, Vec3},
+};
+
+// Places an object in the world with an affine transformation. Rays are
+// intersected in object space, where their direction is left unnormalized so
+// that hit distances are the same in both spaces, and normals are brought back
+// with the inverse transpose of the matrix.
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    normal_matrix: Matrix4,
+    bbox: AABB,
+}
+
+impl Transform {
+    pub fn new(object: Arc<dyn Hittable>, matrix: Matrix4) -> Self {
+        let inverse = matrix
+            .inverse()
+            .expect("Transform matrices must be invertible");
+        let bbox = object.bounding_box();
+        let (min, max) = itertools::iproduct!(
+            [bbox.x.min, bbox.x.max],
+            [bbox.y.min, bbox.y.max],
+            [bbox.z.min, bbox.z.max]
+        )
+        .map(|(x, y, z)| matrix.transform_point(&Point3::new(x, y, z)))
+        .fold(
+            (
+                Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), corner| (min.min(&corner), max.max(&corner)),
+        );
+        Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bbox: AABB::from_extremes(&min, &max),
+        }
+    }
+
+    pub fn translate(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        Transform::new(object, Matrix4::translation(offset))
+    }
+
+    // `angle` is in degrees, counterclockwise around `axis`.
+    pub fn rotate(object: Arc<dyn Hittable>, axis: Vec3, angle: f64) -> Self {
+        Transform::new(object, Matrix4::rotation(axis, angle))
+    }
+
+    pub fn rotate_y(object: Arc<dyn Hittable>, angle: f64) -> Self {
+        Transform::rotate(object, Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn scale(object: Arc<dyn Hittable>, factor: Vec3) -> Self {
+        Transform::new(object, Matrix4::scaling(factor))
+    }
+
+    // Applies `matrix` after this transform, as a single transform of the
+    // same object rather than a chain of wrappers.
+    pub fn then(&self, matrix: Matrix4) -> Self {
+        Transform::new(self.object.clone(), matrix * self.matrix)
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    fn to_object_space(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        )
+    }
+
+    fn to_world_normal(&self, normal: &Vec3) -> Vec3 {
+        self.normal_matrix.transform_vector(normal).unit_vector()
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut record = self
+            .object
+            .hit(&self.to_object_space(ray), interval, sampler)?;
+        // The inverse transpose preserves the sign of the dot product with the
+        // ray direction, so `front_face` stays valid.
+        record.point = self.matrix.transform_point(&record.point);
+        record.normal = self.to_world_normal(&record.normal);
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    // The object's density is with respect to solid angle in object space. It
+    // is converted through the area density at the hit point, using the local
+    // area scaling `|det M| |M^-T n|` of the surface (Nanson's formula).
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = self.to_object_space(&Ray::new(*origin, *direction, 0.0));
+        let object_pdf = self.object.pdf_value(ray.origin(), ray.direction());
+        if object_pdf <= 0.0 {
+            return 0.0;
+        }
+        let Some(record) = self.object.hit(
+            &ray,
+            &Interval::new(0.001, f64::INFINITY),
+            &mut IndependentSampler::new(0),
+        ) else {
+            return 0.0;
+        };
+        let object_normal = record.normal.unit_vector();
+        let world_normal = self.normal_matrix.transform_vector(&object_normal);
+        let area_scale = self.matrix.determinant3().abs() * world_normal.length();
+
+        let object_cosine = ray.direction().unit_vector().dot(&object_normal).abs();
+        let world_cosine = direction
+            .unit_vector()
+            .dot(&world_normal.unit_vector())
+            .abs();
+        let object_distance_squared = record.t * record.t * ray.direction().length_squared();
+        let world_distance_squared = record.t * record.t * direction.length_squared();
+
+        let area_pdf = object_pdf * object_cosine / object_distance_squared / area_scale;
+        area_pdf * world_distance_squared / world_cosine
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self
+            .object
+            .random(&self.inverse.transform_point(origin), sampler);
+        self.matrix.transform_vector(&direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color, material::Lambertian, quad::Quad, sampler::IndependentSampler, sphere::Sphere,
+    };
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::stationary(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::from_albedo(Color::default())),
+        ))
+    }
+
+    #[test]
+    fn test_scaled_sphere_hit_and_normal() {
+        let mut sampler = IndependentSampler::new(0);
+        let ellipsoid = Transform::scale(unit_sphere(), Vec3::new(2.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let record = ellipsoid
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert!((record.t - 3.0).abs() < 1e-9);
+        assert!((record.point - Point3::new(2.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(record.front_face);
+
+        // Off axis, the normal of the ellipsoid is not the scaled normal of
+        // the sphere.
+        let point = Point3::new(2.0 * 0.6, 0.8, 0.0);
+        let ray = Ray::new(
+            point + Vec3::new(0.0, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            0.0,
+        );
+        let record = ellipsoid
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        let expected = Vec3::new(1.2 / 4.0, 0.8, 0.0).unit_vector();
+        assert!((record.normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_bounding_box_of_rotated_object() {
+        let quad = Arc::new(Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::from_albedo(Color::default())),
+        ));
+        let rotated = Transform::rotate(quad, Vec3::new(0.0, 0.0, 1.0), 45.0)
+            .then(Matrix4::translation(Vec3::new(0.0, 0.0, 2.0)));
+        let bbox = rotated.bounding_box();
+        let half_diagonal = f64::sqrt(0.5);
+        assert!((bbox.x.min + half_diagonal).abs() < 1e-9);
+        assert!((bbox.x.max - half_diagonal).abs() < 1e-9);
+        assert!((bbox.y.max - 2.0 * half_diagonal).abs() < 1e-9);
+        assert!(bbox.z.min < 2.0 && bbox.z.max > 2.0);
+    }
+
+    #[test]
+    fn test_pdf_of_scaled_light_matches_solid_angle() {
+        let quad = Arc::new(Quad::new(
+            Point3::new(-0.5, -0.5, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::from_albedo(Color::default())),
+        ));
+        let scaled = Transform::scale(quad, Vec3::new(2.0, 3.0, 1.0));
+        let origin = Point3::new(0.0, 0.0, 10.0);
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        // A light of area 6 seen head on from a distance of 10.
+        let expected = 100.0 / 6.0;
+        assert!((scaled.pdf_value(&origin, &direction) - expected).abs() < 1e-6);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..20 {
+            let direction = scaled.random(&origin, &mut sampler);
+            assert!(scaled.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+}
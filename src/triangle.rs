@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::{IndependentSampler, Sampler},
+    vec3::{Point3, Vec3},
+};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normal: Vec3,
+    area: f64,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Arc<dyn Material>) -> Self {
+        let n = (b - a).cross(&(c - a));
+        Triangle {
+            vertices: [a, b, c],
+            normal: n.unit_vector(),
+            area: 0.5 * n.length(),
+            material,
+            bbox: triangle_bounding_box(&a, &b, &c),
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, interval: &Interval, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let [a, b, c] = &self.vertices;
+        let (t, u, v) = intersect_triangle(ray, interval, a, b, c)?;
+        let mut record = HitRecord::new(
+            ray.at(t),
+            self.normal,
+            Arc::clone(&self.material),
+            t,
+            u,
+            v,
+            false,
+        );
+        record.set_face_normal(ray, &self.normal);
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(
+            &ray,
+            &Interval::new(0.001, f64::INFINITY),
+            &mut IndependentSampler::new(0),
+        ) {
+            Some(record) => {
+                let distance_squared = record.t * record.t * direction.length_squared();
+                let cosine = (direction.dot(&record.normal) / direction.length()).abs();
+                distance_squared / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let [a, b, c] = &self.vertices;
+        let sqrt_r1 = sampler.random().sqrt();
+        let r2 = sampler.random();
+        let point = (1.0 - sqrt_r1) * *a + (sqrt_r1 * (1.0 - r2)) * *b + (sqrt_r1 * r2) * *c;
+        point - *origin
+    }
+}
+
+pub(crate) fn triangle_bounding_box(a: &Point3, b: &Point3, c: &Point3) -> AABB {
+    AABB::from_extremes(&a.min(b).min(c), &a.max(b).max(c))
+}
+
+// Möller–Trumbore intersection. Returns the ray parameter and the barycentric
+// coordinates of the hit with respect to `b` and `c`.
+pub(crate) fn intersect_triangle(
+    ray: &Ray,
+    interval: &Interval,
+    a: &Point3,
+    b: &Point3,
+    c: &Point3,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *b - *a;
+    let edge2 = *c - *a;
+    let p = ray.direction().cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = *ray.origin() - *a;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = ray.direction().dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&q) * inverse_determinant;
+    if !interval.surrounds(t) {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian, sampler::IndependentSampler};
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Point3::new(0.0, 0.0, -1.0),
+            Point3::new(1.0, 0.0, -1.0),
+            Point3::new(0.0, 1.0, -1.0),
+            Arc::new(Lambertian::from_albedo(Color::default())),
+        )
+    }
+
+    #[test]
+    fn test_ray_hits_triangle() {
+        let mut sampler = IndependentSampler::new(0);
+        let ray = Ray::new(Point3::new(0.25, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let record = triangle()
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert!((record.t - 1.0).abs() < 1e-9);
+        assert!((record.u - 0.25).abs() < 1e-9, "Unexpected u: {}", record.u);
+        assert!((record.v - 0.5).abs() < 1e-9, "Unexpected v: {}", record.v);
+        assert!(record.front_face);
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_ray_hits_back_face() {
+        let mut sampler = IndependentSampler::new(0);
+        let ray = Ray::new(Point3::new(0.25, 0.25, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let record = triangle()
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut sampler)
+            .unwrap();
+        assert!(!record.front_face);
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_ray_misses_triangle() {
+        let mut sampler = IndependentSampler::new(0);
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let outside = Ray::new(Point3::new(0.75, 0.75, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(triangle().hit(&outside, &interval, &mut sampler).is_none());
+        let parallel = Ray::new(Point3::new(0.25, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(triangle().hit(&parallel, &interval, &mut sampler).is_none());
+        let behind = Ray::new(Point3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(triangle().hit(&behind, &interval, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn test_random_points_are_on_triangle() {
+        let triangle = triangle();
+        let origin = Point3::new(0.2, 0.2, 1.0);
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = triangle.random(&origin, &mut sampler);
+            assert!(triangle.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+}
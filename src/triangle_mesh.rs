@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::BVHNode,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    triangle::{intersect_triangle, triangle_bounding_box},
+    vec3::{Point3, Vec3},
+};
+
+struct MeshData {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    faces: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+// A triangle mesh with shared vertex buffers. Every face indexes the same
+// position, normal and texture coordinate for a vertex. When normals are given
+// they are interpolated across faces for smooth shading, and texture
+// coordinates replace the barycentric `u` and `v` of the hit.
+pub struct TriangleMesh {
+    bvh: Option<BVHNode>,
+    bbox: AABB,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        faces: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        TriangleMesh::with_attributes(positions, None, None, faces, material)
+    }
+
+    pub fn with_attributes(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        faces: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let vertex_count = positions.len();
+        assert!(
+            faces.iter().flatten().all(|index| *index < vertex_count),
+            "Face indices must be smaller than the number of vertices ({vertex_count})"
+        );
+        if let Some(normals) = &normals {
+            assert_eq!(
+                normals.len(),
+                vertex_count,
+                "Expected one normal per vertex"
+            );
+        }
+        if let Some(uvs) = &uvs {
+            assert_eq!(uvs.len(), vertex_count, "Expected one uv per vertex");
+        }
+
+        let face_count = faces.len();
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            faces,
+            material,
+        });
+        let mut triangles = HittableList::new();
+        for face in 0..face_count {
+            triangles.add(Arc::new(MeshTriangle::new(mesh.clone(), face)));
+        }
+        if face_count == 0 {
+            return TriangleMesh {
+                bvh: None,
+                bbox: AABB::empty(),
+            };
+        }
+        let bvh = BVHNode::new(&mut triangles);
+        let bbox = *bvh.bounding_box();
+        TriangleMesh {
+            bvh: Some(bvh),
+            bbox,
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, interval: &Interval, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.bvh.as_ref()?.hit(ray, interval, sampler)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+    bbox: AABB,
+}
+
+impl MeshTriangle {
+    fn new(mesh: Arc<MeshData>, face: usize) -> Self {
+        let [a, b, c] = mesh.faces[face].map(|index| mesh.positions[index]);
+        let bbox = triangle_bounding_box(&a, &b, &c);
+        MeshTriangle { mesh, face, bbox }
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, interval: &Interval, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let indices = self.mesh.faces[self.face];
+        let [a, b, c] = indices.map(|index| &self.mesh.positions[index]);
+        let (t, u, v) = intersect_triangle(ray, interval, a, b, c)?;
+        let weights = [1.0 - u - v, u, v];
+
+        let (texture_u, texture_v) = match &self.mesh.uvs {
+            Some(uvs) => (0..3).fold((0.0, 0.0), |(tu, tv), i| {
+                let (vertex_u, vertex_v) = uvs[indices[i]];
+                (tu + weights[i] * vertex_u, tv + weights[i] * vertex_v)
+            }),
+            None => (u, v),
+        };
+
+        let geometric_normal = (*b - *a).cross(&(*c - *a)).unit_vector();
+        let mut record = HitRecord::new(
+            ray.at(t),
+            geometric_normal,
+            Arc::clone(&self.mesh.material),
+            t,
+            texture_u,
+            texture_v,
+            false,
+        );
+        record.set_face_normal(ray, &geometric_normal);
+        if let Some(normals) = &self.mesh.normals {
+            let shading_normal = (0..3)
+                .map(|i| weights[i] * normals[indices[i]])
+                .sum::<Vec3>()
+                .unit_vector();
+            record.normal = if record.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian, sampler::IndependentSampler};
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::from_albedo(Color::default()))
+    }
+
+    fn square_positions() -> Vec<Point3> {
+        vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    fn down(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn test_hits_both_faces_of_square() {
+        let mut sampler = IndependentSampler::new(0);
+        let mesh = TriangleMesh::new(square_positions(), vec![[0, 1, 2], [0, 2, 3]], material());
+        let interval = Interval::new(0.001, f64::INFINITY);
+        assert!(
+            mesh.hit(&down(0.75, 0.25), &interval, &mut sampler)
+                .is_some()
+        );
+        assert!(
+            mesh.hit(&down(0.25, 0.75), &interval, &mut sampler)
+                .is_some()
+        );
+        assert!(mesh.hit(&down(1.5, 0.5), &interval, &mut sampler).is_none());
+        let bbox = mesh.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max), (0.0, 1.0));
+        assert_eq!((bbox.y.min, bbox.y.max), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_interpolates_uvs_and_normals() {
+        let mut sampler = IndependentSampler::new(0);
+        let normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0).unit_vector(),
+            Vec3::new(1.0, 0.0, 1.0).unit_vector(),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let uvs = vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        let mesh = TriangleMesh::with_attributes(
+            square_positions(),
+            Some(normals),
+            Some(uvs),
+            vec![[0, 1, 2], [0, 2, 3]],
+            material(),
+        );
+        let record = mesh
+            .hit(
+                &down(0.5, 0.25),
+                &Interval::new(0.001, f64::INFINITY),
+                &mut sampler,
+            )
+            .unwrap();
+        assert!((record.u - 1.0).abs() < 1e-9, "Unexpected u: {}", record.u);
+        assert!((record.v - 0.5).abs() < 1e-9, "Unexpected v: {}", record.v);
+        assert!(record.normal.x > 0.0 && record.normal.z > 0.0);
+        assert!((record.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_empty_mesh_is_never_hit() {
+        let mut sampler = IndependentSampler::new(0);
+        let mesh = TriangleMesh::new(Vec::new(), Vec::new(), material());
+        assert!(
+            mesh.hit(
+                &down(0.5, 0.5),
+                &Interval::new(0.001, f64::INFINITY),
+                &mut sampler
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_face_index_out_of_range() {
+        TriangleMesh::new(square_positions(), vec![[0, 1, 4]], material());
+    }
+}
@@ -3,9 +3,10 @@ use std::{
     ops::{Add, Div, Mul, Neg, Sub},
 };
 
-use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+use crate::sampler::Sampler;
+
 #[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Vec3 {
     pub x: f64,
@@ -18,6 +19,14 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
+    pub fn axis(&self, axis: u8) -> f64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
     pub fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
@@ -62,58 +71,65 @@ impl Vec3 {
         *self / self.length()
     }
 
-    pub fn random() -> Vec3 {
-        let mut rng = rand::rng();
+    pub fn random(sampler: &mut dyn Sampler) -> Vec3 {
         Vec3 {
-            x: rng.random_range(0.0..1.0),
-            y: rng.random_range(0.0..1.0),
-            z: rng.random_range(0.0..1.0),
+            x: sampler.random(),
+            y: sampler.random(),
+            z: sampler.random(),
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        let theta = rand::random::<f64>() * 2.0 * std::f64::consts::PI;
-        let z = rand::random::<f64>() * 2.0 - 1.0;
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.random_2d();
+        let theta = u * 2.0 * std::f64::consts::PI;
+        let z = v * 2.0 - 1.0;
         let r = (1.0 - z * z).sqrt();
         Vec3::new(r * theta.cos(), r * theta.sin(), z)
-        // loop {
-        //     let candidate = Vec3::random();
-        //     let length = candidate.length_squared();
-        //     if 1e-160 < length && length <= 1.0 {
-        //         return candidate / length.sqrt();
-        //     }
-        // }
-    }
-
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::rng();
-        loop {
-            let candidate = Vec3::new(
-                rng.random_range(-1.0..1.0),
-                rng.random_range(-1.0..1.0),
-                0.0,
-            );
-            if candidate.length() < 1.0 {
-                return candidate;
-            }
+    }
+
+    pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.random_2d();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+        Vec3::new(x, y, z)
+    }
+
+    // Shirley and Chiu's concentric mapping of the square to the disk, which
+    // keeps stratified samples stratified.
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.random_2d();
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::default();
         }
+        let (radius, phi) = if a.abs() > b.abs() {
+            (a, std::f64::consts::FRAC_PI_4 * (b / a))
+        } else {
+            (
+                b,
+                std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (a / b),
+            )
+        };
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), 0.0)
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let on_unit_sphere: Vec3 = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(normal: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let on_unit_sphere: Vec3 = Vec3::random_unit_vector(sampler);
         if on_unit_sphere.dot(normal) > 0.0 {
-            return on_unit_sphere;
+            on_unit_sphere
         } else {
-            return -on_unit_sphere;
+            -on_unit_sphere
         }
     }
 
     pub fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
-        *incident - 2.0 * incident.dot(&normal) * *normal
+        *incident - 2.0 * incident.dot(normal) * *normal
     }
 
     pub fn refract(incident: &Vec3, normal: &Vec3, eta_ratio: f64) -> Vec3 {
-        let cos_theta = (-*incident).dot(&normal).min(1.0);
+        let cos_theta = (-*incident).dot(normal).min(1.0);
         let ray_out_perpendicular = eta_ratio * (*incident + *normal * cos_theta);
         let ray_out_parallel =
             *normal * -(1.0 - ray_out_perpendicular.length_squared()).abs().sqrt();
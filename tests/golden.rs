@@ -0,0 +1,155 @@
+// Renders every built-in scene at a low resolution with a fixed seed and
+// compares it with its reference image in tests/golden. After an intended
+// change of the output, the references are rewritten with
+//
+//     UPDATE_GOLDEN=1 cargo test --test golden
+//
+// and should be looked at before being committed.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use image::{Rgb, RgbImage};
+
+use ray_tracer::bvh::SplitStrategy;
+use ray_tracer::camera::{Camera, CameraSettings};
+use ray_tracer::color::color_to_bytes;
+use ray_tracer::scene::Scene;
+
+const SEED: u64 = 1;
+const IMAGE_WIDTH: i32 = 48;
+const SAMPLES_PER_PIXEL: i32 = 16;
+const MAX_DEPTH: i32 = 8;
+
+// Renders are bit-identical on a given platform, but the last bits of some
+// floating-point functions vary between platforms and can send a few paths
+// elsewhere. Rendering with another seed scores 15 to 35 dB.
+const MIN_PSNR: f64 = 40.0;
+
+fn golden_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn render(scene: &Scene) -> RgbImage {
+    let settings_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("settings/camera")
+        .join(format!("{}.json", scene.to_str()));
+    let contents = fs::read_to_string(settings_path).unwrap();
+    let mut settings: CameraSettings = serde_json::from_str(&contents).unwrap();
+    settings.image_width = IMAGE_WIDTH;
+    settings.samples_per_pixel = SAMPLES_PER_PIXEL;
+    settings.max_depth = MAX_DEPTH;
+
+    let objects = scene.build(SplitStrategy::default(), SEED);
+    let framebuffer = Camera::from_settings(settings)
+        .with_seed(SEED)
+        .render_framebuffer(&objects.world, &objects.lights);
+    RgbImage::from_fn(framebuffer.width(), framebuffer.height(), |x, y| {
+        Rgb(color_to_bytes(&framebuffer.pixel(x, y)))
+    })
+}
+
+// Root mean squared error of the channels, scaled to [0, 1].
+fn rmse(image: &RgbImage, reference: &RgbImage) -> f64 {
+    let squared_error: f64 = image
+        .as_raw()
+        .iter()
+        .zip(reference.as_raw())
+        .map(|(a, b)| ((*a as f64 - *b as f64) / 255.0).powi(2))
+        .sum();
+    (squared_error / image.as_raw().len() as f64).sqrt()
+}
+
+// Peak signal-to-noise ratio in decibels, infinite for identical images.
+fn psnr(image: &RgbImage, reference: &RgbImage) -> f64 {
+    20.0 * (1.0 / rmse(image, reference)).log10()
+}
+
+// The absolute difference of each channel, amplified to show small errors.
+fn diff_image(image: &RgbImage, reference: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let (a, b) = (image.get_pixel(x, y), reference.get_pixel(x, y));
+        Rgb(std::array::from_fn(|c| {
+            a[c].abs_diff(b[c]).saturating_mul(4)
+        }))
+    })
+}
+
+// Returns a description of the mismatch, if any, after saving the render and
+// its difference with the reference next to the test binaries.
+fn check(scene: &Scene, update: bool) -> Option<String> {
+    let name = scene.to_str();
+    let image = render(scene);
+    let reference_path = golden_directory().join(format!("{name}.png"));
+    if update {
+        image.save(&reference_path).unwrap();
+        return None;
+    }
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgb8(),
+        Err(error) => {
+            return Some(format!(
+                "{name}: cannot read {}: {error} (run with UPDATE_GOLDEN=1 to create it)",
+                reference_path.display()
+            ));
+        }
+    };
+    if image.dimensions() != reference.dimensions() {
+        return Some(format!(
+            "{name}: rendered {:?} pixels, the reference has {:?}",
+            image.dimensions(),
+            reference.dimensions()
+        ));
+    }
+    let psnr = psnr(&image, &reference);
+    if psnr >= MIN_PSNR {
+        return None;
+    }
+    let output_directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&output_directory).unwrap();
+    image
+        .save(output_directory.join(format!("{name}-actual.png")))
+        .unwrap();
+    diff_image(&image, &reference)
+        .save(output_directory.join(format!("{name}-diff.png")))
+        .unwrap();
+    Some(format!(
+        "{name}: PSNR {psnr:.1} dB (RMSE {:.4}) is below {MIN_PSNR} dB, see {}",
+        rmse(&image, &reference),
+        output_directory.display()
+    ))
+}
+
+#[test]
+fn test_scenes_match_golden_images() {
+    // SAFETY: no other test of this binary reads the environment.
+    unsafe {
+        env::set_var(
+            "EARTH_IMAGE_PATH",
+            golden_directory().join("earth_texture.png"),
+        );
+    }
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let failures = Scene::value_variants()
+        .iter()
+        .filter_map(|scene| check(scene, update))
+        .collect::<Vec<String>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_psnr() {
+    let black = RgbImage::new(4, 4);
+    let mut grey = black.clone();
+    grey.pixels_mut()
+        .for_each(|pixel| *pixel = Rgb([51, 51, 51]));
+    assert!((rmse(&grey, &black) - 0.2).abs() < 1e-12);
+    assert!((psnr(&grey, &black) - 13.979).abs() < 1e-3);
+    assert_eq!(psnr(&black, &black), f64::INFINITY);
+    assert_eq!(
+        diff_image(&grey, &black).get_pixel(0, 0),
+        &Rgb([204, 204, 204])
+    );
+}